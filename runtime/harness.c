@@ -0,0 +1,15 @@
+/* Harness for running programs produced by the L1 compiler.
+ *
+ * Build a test program with
+ *
+ *     gcc runtime/harness.c foo.s -o foo
+ */
+
+#include <stdio.h>
+
+extern int _c0_main(void);
+
+int main(void) {
+    printf("%d\n", _c0_main());
+    return 0;
+}
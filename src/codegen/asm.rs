@@ -1,7 +1,8 @@
 //! Assembly language
 //!
-//! Currently just a pseudo-language with 3-operand instructions and arbitrarily
-//! many temps
+//! Two-address x86-64 instructions printed in AT&T syntax. Operands may still
+//! refer to temps, which must be assigned a location before the assembly is
+//! emitted.
 
 use std::fmt;
 
@@ -9,10 +10,41 @@ use util::Temp;
 
 #[derive(Clone)]
 pub enum Instruction {
-    Binop(Op, Operand, Operand, Operand),
+    /// `dst <- dst op src`
+    Binop(Op, Operand, Operand),
+    /// `dst <- src`
     Mov(Operand, Operand),
+    /// `dst <- -dst`
+    Neg(Operand),
+    /// `dst <- ~dst`
+    Not(Operand),
+    /// `dst <- address`, which does arithmetic without touching memory or
+    /// the flags
+    Lea(Operand, Address),
+    /// Multiplies `%eax` by the operand, leaving the 64-bit product in
+    /// `%edx:%eax`
+    Imul(Operand),
+    /// Sign-extends `%eax` into `%edx`
+    Cltd,
+    /// Divides `%edx:%eax` by the operand, leaving the quotient in `%eax` and
+    /// the remainder in `%edx`
+    Idiv(Operand),
+    /// Sets the condition flags according to `lhs - rhs`
+    Cmp(Operand, Operand),
+    /// `dst <- 1` if the condition holds and `dst <- 0` otherwise, which takes
+    /// a `set` and a `movzbl`
+    Setcc(Cond, Operand),
+    Jmp(String),
+    /// Jumps to the label if the condition holds
+    Jcc(Cond, String),
+    Push(Register),
+    Pop(Register),
+    /// Reserves the given number of bytes on the stack
+    AllocStack(u32),
+    /// Releases the given number of bytes from the stack
+    FreeStack(u32),
+    Ret,
     Directive(String),
-    Comment(String),
     Label(String),
 }
 
@@ -21,23 +53,150 @@ pub enum Operand {
     Imm(u32),
     Reg(Register),
     Temp(Temp),
+    /// A 4-byte stack slot, numbered from the bottom of the frame
+    Stack(usize),
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum Register { EAX }
+/// The address `disp(base, index, scale)`, which is
+/// `base + index * scale + disp`. The base and index must end up in registers.
+#[derive(Clone)]
+pub struct Address {
+    pub disp: u32,
+    pub base: Option<Operand>,
+    pub index: Option<Operand>,
+    /// 1, 2, 4 or 8
+    pub scale: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    Eax, Ebx, Ecx, Edx, Esi, Edi, Ebp,
+    R8d, R9d, R10d, R11d, R12d, R13d, R14d, R15d,
+}
 
+/// Two-address operators. The shifts take their count from `%cl` or an
+/// immediate, and `Shr` shifts in zeros where `Sar` copies the sign bit.
 #[derive(Clone)]
-pub enum Op { Add, Sub, Mul, Div, Mod }
+pub enum Op { Add, Sub, Mul, And, Or, Xor, Sal, Sar, Shr }
+
+/// Conditions on the flags set by a comparison. All of them are signed except
+/// `A`, which holds if the left-hand side is above the right as unsigned.
+#[derive(Copy, Clone)]
+pub enum Cond { E, Ne, L, Le, G, Ge, A }
+
+impl Instruction {
+    /// Rewrites every operand of this instruction with `f`.
+    pub fn map_operands<F: FnMut(Operand) -> Operand>(self, mut f: F)
+                                                     -> Instruction {
+        match self {
+            Instruction::Binop(op, d, s) => Instruction::Binop(op, f(d), f(s)),
+            Instruction::Mov(d, s) => Instruction::Mov(f(d), f(s)),
+            Instruction::Neg(d) => Instruction::Neg(f(d)),
+            Instruction::Not(d) => Instruction::Not(f(d)),
+            Instruction::Lea(d, a) => {
+                let d = f(d);
+                let base = a.base.map(&mut f);
+                let index = a.index.map(&mut f);
+                Instruction::Lea(d, Address { base, index, ..a })
+            }
+            Instruction::Imul(s) => Instruction::Imul(f(s)),
+            Instruction::Idiv(s) => Instruction::Idiv(f(s)),
+            Instruction::Cmp(l, r) => Instruction::Cmp(f(l), f(r)),
+            Instruction::Setcc(c, d) => Instruction::Setcc(c, f(d)),
+            i => i,
+        }
+    }
+}
+
+impl Operand {
+    /// Returns whether this operand refers to memory.
+    pub fn is_mem(&self) -> bool {
+        matches!(*self, Operand::Stack(..))
+    }
+}
+
+impl Register {
+    /// Returns whether a function must preserve this register for its caller.
+    pub fn is_callee_saved(&self) -> bool {
+        matches!(*self, Register::Ebx | Register::Ebp | Register::R12d |
+                        Register::R13d | Register::R14d | Register::R15d)
+    }
+
+    /// Returns the name of the lowest byte of the register, as needed by
+    /// `set` and the shifts.
+    pub fn byte(&self) -> &'static str {
+        match *self {
+            Register::Eax => "%al",
+            Register::Ebx => "%bl",
+            Register::Ecx => "%cl",
+            Register::Edx => "%dl",
+            Register::Esi => "%sil",
+            Register::Edi => "%dil",
+            Register::Ebp => "%bpl",
+            Register::R8d => "%r8b",
+            Register::R9d => "%r9b",
+            Register::R10d => "%r10b",
+            Register::R11d => "%r11b",
+            Register::R12d => "%r12b",
+            Register::R13d => "%r13b",
+            Register::R14d => "%r14b",
+            Register::R15d => "%r15b",
+        }
+    }
+
+    /// Returns the name of the full 64-bit register, as needed by `push`,
+    /// `pop` and addresses.
+    pub fn quad(&self) -> &'static str {
+        match *self {
+            Register::Eax => "%rax",
+            Register::Ebx => "%rbx",
+            Register::Ecx => "%rcx",
+            Register::Edx => "%rdx",
+            Register::Esi => "%rsi",
+            Register::Edi => "%rdi",
+            Register::Ebp => "%rbp",
+            Register::R8d => "%r8",
+            Register::R9d => "%r9",
+            Register::R10d => "%r10",
+            Register::R11d => "%r11",
+            Register::R12d => "%r12",
+            Register::R13d => "%r13",
+            Register::R14d => "%r14",
+            Register::R15d => "%r15",
+        }
+    }
+}
 
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Instruction::Label(ref s) => write!(f, "{}:", s),
-            Instruction::Binop(ref op, ref d, ref s1, ref s2) =>
-                write!(f, "\t{} <-- {} {} {}", d, s1, op, s2),
-            Instruction::Mov(ref d, ref s) => write!(f, "\t{} <-- {}", d, s),
+            Instruction::Binop(ref op @ Op::Sal, ref d, Operand::Reg(ref r)) |
+            Instruction::Binop(ref op @ Op::Sar, ref d, Operand::Reg(ref r)) |
+            Instruction::Binop(ref op @ Op::Shr, ref d, Operand::Reg(ref r)) =>
+                write!(f, "\t{} {}, {}", op, r.byte(), d),
+            Instruction::Binop(ref op, ref d, ref s) =>
+                write!(f, "\t{} {}, {}", op, s, d),
+            Instruction::Mov(ref d, ref s) => write!(f, "\tmovl {}, {}", s, d),
+            Instruction::Neg(ref d) => write!(f, "\tnegl {}", d),
+            Instruction::Not(ref d) => write!(f, "\tnotl {}", d),
+            Instruction::Lea(ref d, ref a) => write!(f, "\tleal {}, {}", a, d),
+            Instruction::Imul(ref s) => write!(f, "\timull {}", s),
+            Instruction::Cltd => write!(f, "\tcltd"),
+            Instruction::Idiv(ref s) => write!(f, "\tidivl {}", s),
+            Instruction::Cmp(ref l, ref r) => write!(f, "\tcmpl {}, {}", r, l),
+            Instruction::Setcc(ref c, Operand::Reg(ref r)) => {
+                write!(f, "\tset{} {}\n\tmovzbl {}, {}", c, r.byte(), r.byte(), r)
+            }
+            Instruction::Setcc(ref c, ref d) => write!(f, "\tset{} {}", c, d),
+            Instruction::Jmp(ref l) => write!(f, "\tjmp {}", l),
+            Instruction::Jcc(ref c, ref l) => write!(f, "\tj{} {}", c, l),
+            Instruction::Push(ref r) => write!(f, "\tpushq {}", r.quad()),
+            Instruction::Pop(ref r) => write!(f, "\tpopq {}", r.quad()),
+            Instruction::AllocStack(n) => write!(f, "\tsubq ${}, %rsp", n),
+            Instruction::FreeStack(n) => write!(f, "\taddq ${}, %rsp", n),
+            Instruction::Ret => write!(f, "\tret"),
             Instruction::Directive(ref s) => write!(f, "\t{}", s),
-            Instruction::Comment(ref s) => write!(f, "\t/* {} */", s),
         }
 
     }
@@ -49,14 +208,62 @@ impl fmt::Display for Operand {
             Operand::Imm(c) => write!(f, "${}", c),
             Operand::Temp(t) => write!(f, "{}", t),
             Operand::Reg(ref r) => write!(f, "{}", r),
+            Operand::Stack(i) => write!(f, "{}(%rsp)", 4 * i),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Addresses are computed from the full 64-bit registers
+        let part = |o: &Option<Operand>| match *o {
+            Some(Operand::Reg(ref r)) => r.quad().to_string(),
+            Some(ref o) => o.to_string(),
+            None => String::new(),
+        };
+        if self.disp != 0 {
+            write!(f, "{}", self.disp as i32)?;
+        }
+        write!(f, "({}", part(&self.base))?;
+        if self.index.is_some() {
+            write!(f, ",{},{}", part(&self.index), self.scale)?;
         }
+        write!(f, ")")
     }
 }
 
 impl fmt::Display for Register {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Register::EAX => "%eax".fmt(f),
+            Register::Eax => "%eax".fmt(f),
+            Register::Ebx => "%ebx".fmt(f),
+            Register::Ecx => "%ecx".fmt(f),
+            Register::Edx => "%edx".fmt(f),
+            Register::Esi => "%esi".fmt(f),
+            Register::Edi => "%edi".fmt(f),
+            Register::Ebp => "%ebp".fmt(f),
+            Register::R8d => "%r8d".fmt(f),
+            Register::R9d => "%r9d".fmt(f),
+            Register::R10d => "%r10d".fmt(f),
+            Register::R11d => "%r11d".fmt(f),
+            Register::R12d => "%r12d".fmt(f),
+            Register::R13d => "%r13d".fmt(f),
+            Register::R14d => "%r14d".fmt(f),
+            Register::R15d => "%r15d".fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Cond::E => "e".fmt(f),
+            Cond::Ne => "ne".fmt(f),
+            Cond::L => "l".fmt(f),
+            Cond::Le => "le".fmt(f),
+            Cond::G => "g".fmt(f),
+            Cond::Ge => "ge".fmt(f),
+            Cond::A => "a".fmt(f),
         }
     }
 }
@@ -64,11 +271,15 @@ impl fmt::Display for Register {
 impl fmt::Display for Op {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Op::Add => "+".fmt(f),
-            Op::Sub => "-".fmt(f),
-            Op::Mul => "*".fmt(f),
-            Op::Div => "/".fmt(f),
-            Op::Mod => "%".fmt(f),
+            Op::Add => "addl".fmt(f),
+            Op::Sub => "subl".fmt(f),
+            Op::Mul => "imull".fmt(f),
+            Op::And => "andl".fmt(f),
+            Op::Or => "orl".fmt(f),
+            Op::Xor => "xorl".fmt(f),
+            Op::Sal => "sall".fmt(f),
+            Op::Sar => "sarl".fmt(f),
+            Op::Shr => "shrl".fmt(f),
         }
     }
 }
@@ -0,0 +1,165 @@
+//! Stack frames
+//!
+//! Rewrites instructions whose operands x86-64 cannot encode, and wraps the
+//! function body in a prologue and epilogue which reserve its stack slots and
+//! preserve the callee-saved registers it uses.
+
+use std::collections::BTreeSet;
+
+use codegen::asm::{Address, Instruction, Operand, Register, Op};
+
+/// Register reserved for shuffling values between two memory operands.
+const SCRATCH: Operand = Operand::Reg(Register::R11d);
+
+/// Turns the allocated body of a function into assembly that can be emitted.
+pub fn finish(ins: Vec<Instruction>) -> Vec<Instruction> {
+    let mut slots = 0;
+    let mut saved = BTreeSet::new();
+    for i in ins.iter() {
+        i.clone().map_operands(|o| {
+            match o {
+                Operand::Stack(n) if n >= slots => slots = n + 1,
+                Operand::Reg(r) if r.is_callee_saved() => { saved.insert(r); }
+                _ => {}
+            }
+            o
+        });
+    }
+    let saved = saved.into_iter().collect::<Vec<_>>();
+
+    let size = frame_size(slots, saved.len());
+    let mut out = Vec::new();
+    for &r in saved.iter() {
+        out.push(Instruction::Push(r));
+    }
+    if size > 0 {
+        out.push(Instruction::AllocStack(size));
+    }
+    for i in ins {
+        legalize(i, size, &saved, &mut out);
+    }
+    out
+}
+
+/// Returns the number of bytes to reserve for `slots` stack slots after
+/// `pushes` registers have been saved.
+///
+/// The call into the function pushed an 8-byte return address, so the frame is
+/// sized to bring `%rsp` back to a 16-byte boundary.
+fn frame_size(slots: usize, pushes: usize) -> u32 {
+    if slots == 0 {
+        return 0
+    }
+    let pushed = 8 * pushes as u32 + 8;
+    let bytes = 4 * slots as u32 + pushed;
+    bytes.div_ceil(16) * 16 - pushed
+}
+
+/// Appends `i` to `out`, splitting it up if it refers to more memory than the
+/// instruction can encode.
+fn legalize(i: Instruction, size: u32, saved: &[Register],
+            out: &mut Vec<Instruction>) {
+    match i {
+        Instruction::Mov(d, s) => {
+            if d.is_mem() && s.is_mem() {
+                out.push(Instruction::Mov(SCRATCH, s));
+                out.push(Instruction::Mov(d, SCRATCH));
+            } else {
+                out.push(Instruction::Mov(d, s));
+            }
+        }
+        // imull can only write to a register
+        Instruction::Binop(Op::Mul, d, s) if d.is_mem() => {
+            out.push(Instruction::Mov(SCRATCH, d.clone()));
+            out.push(Instruction::Binop(Op::Mul, SCRATCH, s));
+            out.push(Instruction::Mov(d, SCRATCH));
+        }
+        Instruction::Binop(op, d, s) => {
+            if d.is_mem() && s.is_mem() {
+                out.push(Instruction::Mov(SCRATCH, s));
+                out.push(Instruction::Binop(op, d, SCRATCH));
+            } else {
+                out.push(Instruction::Binop(op, d, s));
+            }
+        }
+        // The left-hand side of cmpl cannot be an immediate
+        Instruction::Cmp(l @ Operand::Imm(..), r) => {
+            out.push(Instruction::Mov(SCRATCH, l));
+            out.push(Instruction::Cmp(SCRATCH, r));
+        }
+        Instruction::Cmp(l, r) => {
+            if l.is_mem() && r.is_mem() {
+                out.push(Instruction::Mov(SCRATCH, r));
+                out.push(Instruction::Cmp(l, SCRATCH));
+            } else {
+                out.push(Instruction::Cmp(l, r));
+            }
+        }
+        // leal can only write to a register, and the parts of the address
+        // have to be registers. Failing that, the address is worked out in
+        // the scratch register with ordinary arithmetic.
+        Instruction::Lea(d, a) => {
+            let in_memory = |o: &Option<Operand>| {
+                o.as_ref().is_some_and(Operand::is_mem)
+            };
+            if in_memory(&a.base) || in_memory(&a.index) {
+                let Address { disp, base, index, scale } = a;
+                match (base, index) {
+                    (base, Some(index)) => {
+                        out.push(Instruction::Mov(SCRATCH, index));
+                        if scale > 1 {
+                            out.push(Instruction::Binop(Op::Mul, SCRATCH,
+                                                        Operand::Imm(scale)));
+                        }
+                        if let Some(base) = base {
+                            out.push(Instruction::Binop(Op::Add, SCRATCH,
+                                                        base));
+                        }
+                    }
+                    (Some(base), None) => {
+                        out.push(Instruction::Mov(SCRATCH, base))
+                    }
+                    (None, None) => unreachable!(),
+                }
+                if disp != 0 {
+                    out.push(Instruction::Binop(Op::Add, SCRATCH,
+                                                Operand::Imm(disp)));
+                }
+                out.push(Instruction::Mov(d, SCRATCH));
+            } else if d.is_mem() {
+                out.push(Instruction::Lea(SCRATCH, a));
+                out.push(Instruction::Mov(d, SCRATCH));
+            } else {
+                out.push(Instruction::Lea(d, a));
+            }
+        }
+        // set can only write to a register
+        Instruction::Setcc(c, d) => {
+            if d.is_mem() {
+                out.push(Instruction::Setcc(c, SCRATCH));
+                out.push(Instruction::Mov(d, SCRATCH));
+            } else {
+                out.push(Instruction::Setcc(c, d));
+            }
+        }
+        // idivl and the one-operand imull cannot take an immediate
+        Instruction::Idiv(s @ Operand::Imm(..)) => {
+            out.push(Instruction::Mov(SCRATCH, s));
+            out.push(Instruction::Idiv(SCRATCH));
+        }
+        Instruction::Imul(s @ Operand::Imm(..)) => {
+            out.push(Instruction::Mov(SCRATCH, s));
+            out.push(Instruction::Imul(SCRATCH));
+        }
+        Instruction::Ret => {
+            if size > 0 {
+                out.push(Instruction::FreeStack(size));
+            }
+            for &r in saved.iter().rev() {
+                out.push(Instruction::Pop(r));
+            }
+            out.push(Instruction::Ret);
+        }
+        i => out.push(i),
+    }
+}
@@ -0,0 +1,191 @@
+//! Liveness analysis
+//!
+//! Computes, for every instruction of a function, the locations it defines and
+//! uses along with the sets of locations live on entry to and exit from it.
+//! The results are shared by register allocation and the debugging dumps.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use codegen::asm::{Instruction, Operand, Register};
+use util::Temp;
+
+/// A location which can hold a value: either a temp or a specific register
+/// required by some instruction.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Location {
+    Temp(Temp),
+    Reg(Register),
+}
+
+pub struct Liveness {
+    defs: Vec<Vec<Location>>,
+    uses: Vec<Vec<Location>>,
+    live_in: Vec<HashSet<Location>>,
+    live_out: Vec<HashSet<Location>>,
+}
+
+/// Helper for printing each instruction next to its liveness information.
+pub struct Dump<'a> {
+    liveness: &'a Liveness,
+    ins: &'a [Instruction],
+}
+
+impl Liveness {
+    /// Runs the analysis over the body of a function.
+    pub fn analyze(ins: &[Instruction]) -> Liveness {
+        let (defs, uses): (Vec<_>, Vec<_>) = ins.iter().map(def_use).unzip();
+        let succs = successors(ins);
+        let mut live = Liveness {
+            defs,
+            uses,
+            live_in: vec![HashSet::new(); ins.len()],
+            live_out: vec![HashSet::new(); ins.len()],
+        };
+
+        // Iterate to a fixed point, visiting instructions backwards since
+        // information flows from successors to predecessors
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..ins.len()).rev() {
+                let mut out = HashSet::new();
+                for &s in succs[i].iter() {
+                    out.extend(live.live_in[s].iter().cloned());
+                }
+                let mut inn = out.iter().filter(|l| !live.defs[i].contains(l))
+                                 .cloned().collect::<HashSet<_>>();
+                inn.extend(live.uses[i].iter().cloned());
+                if inn.len() != live.live_in[i].len() ||
+                   out.len() != live.live_out[i].len() {
+                    changed = true;
+                }
+                live.live_in[i] = inn;
+                live.live_out[i] = out;
+            }
+        }
+        live
+    }
+
+    /// Locations written by instruction `i`.
+    pub fn defs(&self, i: usize) -> &[Location] { &self.defs[i] }
+
+    /// Locations read by instruction `i`.
+    pub fn uses(&self, i: usize) -> &[Location] { &self.uses[i] }
+
+    /// Locations live immediately before instruction `i`.
+    pub fn live_in(&self, i: usize) -> &HashSet<Location> { &self.live_in[i] }
+
+    /// Locations live immediately after instruction `i`.
+    pub fn live_out(&self, i: usize) -> &HashSet<Location> {
+        &self.live_out[i]
+    }
+
+    /// Pairs the analysis with the instructions it was computed from, for
+    /// printing.
+    pub fn dump<'a>(&'a self, ins: &'a [Instruction]) -> Dump<'a> {
+        Dump { liveness: self, ins }
+    }
+}
+
+/// Converts an operand into the location it names, if any.
+pub fn location(o: &Operand) -> Option<Location> {
+    match *o {
+        Operand::Temp(t) => Some(Location::Temp(t)),
+        Operand::Reg(r) => Some(Location::Reg(r)),
+        _ => None,
+    }
+}
+
+/// Returns the locations defined and used by an instruction.
+fn def_use(i: &Instruction) -> (Vec<Location>, Vec<Location>) {
+    let eax = Location::Reg(Register::Eax);
+    let edx = Location::Reg(Register::Edx);
+    match *i {
+        Instruction::Mov(ref d, ref s) => {
+            (location(d).into_iter().collect(),
+             location(s).into_iter().collect())
+        }
+        Instruction::Binop(_, ref d, ref s) => {
+            let uses = location(d).into_iter().chain(location(s)).collect();
+            (location(d).into_iter().collect(), uses)
+        }
+        Instruction::Neg(ref d) | Instruction::Not(ref d) => {
+            (location(d).into_iter().collect(),
+             location(d).into_iter().collect())
+        }
+        Instruction::Lea(ref d, ref a) => {
+            let uses = a.base.iter().chain(a.index.iter())
+                .filter_map(location).collect();
+            (location(d).into_iter().collect(), uses)
+        }
+        Instruction::Cltd => (vec![edx], vec![eax]),
+        Instruction::Imul(ref s) => {
+            let uses = location(s).into_iter().chain(vec![eax]).collect();
+            (vec![eax, edx], uses)
+        }
+        Instruction::Idiv(ref s) => {
+            let uses = location(s).into_iter().chain(vec![eax, edx]).collect();
+            (vec![eax, edx], uses)
+        }
+        Instruction::Cmp(ref l, ref r) => {
+            (vec![], location(l).into_iter().chain(location(r)).collect())
+        }
+        Instruction::Setcc(_, ref d) => (location(d).into_iter().collect(), vec![]),
+        Instruction::Ret => (vec![], vec![eax]),
+        _ => (vec![], vec![]),
+    }
+}
+
+/// Returns the indices of the instructions which may execute after each
+/// instruction.
+///
+/// Labels are ordinary instructions which fall through to the next one.
+fn successors(ins: &[Instruction]) -> Vec<Vec<usize>> {
+    let labels = ins.iter().enumerate().filter_map(|(i, instr)| {
+        match *instr {
+            Instruction::Label(ref l) => Some((l.clone(), i)),
+            _ => None,
+        }
+    }).collect::<HashMap<_, _>>();
+    let next = |i: usize| if i + 1 < ins.len() { vec![i + 1] } else { vec![] };
+
+    ins.iter().enumerate().map(|(i, instr)| {
+        match *instr {
+            Instruction::Ret => vec![],
+            Instruction::Jmp(ref l) => vec![labels[l]],
+            Instruction::Jcc(_, ref l) => {
+                let mut succs = next(i);
+                succs.push(labels[l]);
+                succs
+            }
+            _ => next(i),
+        }
+    }).collect()
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Location::Temp(ref t) => t.fmt(f),
+            Location::Reg(ref r) => r.fmt(f),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Dump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let set = |s: &HashSet<Location>| {
+            let mut s = s.iter().collect::<Vec<_>>();
+            s.sort();
+            s.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ")
+        };
+        for (i, ins) in self.ins.iter().enumerate() {
+            if i > 0 { writeln!(f)? }
+            write!(f, "{:<32} in: {{{}}} out: {{{}}}", ins.to_string(),
+                        set(self.liveness.live_in(i)),
+                        set(self.liveness.live_out(i)))?;
+        }
+        Ok(())
+    }
+}
@@ -1,69 +1,332 @@
-//! Translation from IR to assembly
+//! Translation from IR2 to assembly
 //!
-//! Currently implements a "convenient munch" algorithm
+//! Instructions are selected by maximal munch: each IR2 instruction is
+//! matched against the largest pattern which covers it, taking the instruction
+//! after it along when a single `leal` can do the work of both. Constants are
+//! used as immediates and temps directly as operands, so a temp which ends up
+//! in a stack slot becomes a memory operand wherever x86-64 allows one.
 
-use middle::ir::{Expr, Binop, Statement, Program};
-use util::TempAllocator;
-use codegen::asm::{Operand, Instruction, Register, Op};
+use std::collections::HashMap;
+
+use middle::ir::Binop;
+use middle::ir2::{self, Block, BlockId, Exit, Program};
+use util::{Label, LabelAllocator, Temp, TempAllocator};
+use codegen::asm::{Address, Operand, Instruction, Register, Op, Cond};
 
 pub mod asm;
+pub mod liveness;
+pub mod peephole;
+mod frame;
+mod regalloc;
+mod strength;
+
+/// Name of the function the runtime harness calls into.
+pub const MAIN: &str = "_c0_main";
 
 struct Translator {
     ins: Vec<Instruction>,
     temps: TempAllocator,
+    labels: LabelAllocator,
+    /// Label of the code raising an arithmetic exception, if any operation
+    /// needs it
+    fpe: Option<Label>,
+    /// Whether to replace multiplication and division by constants with
+    /// cheaper instructions
+    reduce: bool,
 }
 
-pub fn translate(ir: Program) -> Vec<Instruction> {
-    let Program { statements, temps } = ir;
-    let mut translator = Translator::new(temps);
-    translator.ins.push(Instruction::Directive(".ident \"15-411 L1 reference compiler\"".to_string()));
-    for stm in statements.into_iter() {
-        translator.stm(stm);
+/// Selects instructions for a program, leaving its temps unallocated. With
+/// `reduce`, multiplication and division by constants are strength reduced.
+pub fn translate(ir: Program, reduce: bool) -> Vec<Instruction> {
+    let Program { blocks, temps, labels } = ir;
+    let mut translator = Translator::new(temps, labels, reduce);
+    let mentions = mentions(&blocks);
+    for block in blocks.iter() {
+        debug_assert!(block.phis.is_empty(), "phis left in {}", block.label);
+        translator.ins.push(Instruction::Label(block.label.to_string()));
+        let mut body = block.body.iter().peekable();
+        while let Some(i) = body.next() {
+            if let Some(&next) = body.peek() {
+                if translator.scaled_add(i, next, &mentions) {
+                    body.next();
+                    continue
+                }
+            }
+            translator.instruction(i);
+        }
+        translator.exit(&block.exit, &blocks);
+    }
+    // Dividing by zero raises SIGFPE
+    if let Some(l) = translator.fpe {
+        translator.ins.push(Instruction::Label(l.to_string()));
+        translator.ins.push(Instruction::Mov(Operand::Reg(Register::Eax),
+                                             Operand::Imm(0)));
+        translator.ins.push(Instruction::Cltd);
+        translator.ins.push(Instruction::Idiv(Operand::Imm(0)));
     }
     translator.ins
 }
 
+/// Allocates registers for the selected instructions and lays out the
+/// function, producing assembly ready to be written out once the peephole
+/// `rules` have been applied.
+pub fn emit(body: Vec<Instruction>, rules: &[&peephole::Rule])
+            -> Vec<Instruction> {
+    let mut ins = vec![
+        Instruction::Directive(".ident \"15-411 L1 reference compiler\"".to_string()),
+        Instruction::Directive(".text".to_string()),
+        Instruction::Directive(format!(".globl {}", MAIN)),
+        Instruction::Label(MAIN.to_string()),
+    ];
+    let body = frame::finish(regalloc::allocate(body));
+    ins.extend(peephole::optimize(body, rules));
+    // Tell the linker this code does not need an executable stack
+    ins.push(Instruction::Directive(
+        ".section .note.GNU-stack,\"\",@progbits".to_string()));
+    ins
+}
+
 impl Translator {
-    fn new(temps: TempAllocator) -> Translator {
-        Translator { ins: Vec::new(), temps: temps }
+    fn new(temps: TempAllocator, labels: LabelAllocator, reduce: bool)
+           -> Translator {
+        Translator {
+            ins: Vec::new(),
+            temps,
+            labels,
+            fpe: None,
+            reduce,
+        }
+    }
+
+    /// Returns the label to jump to to raise an arithmetic exception.
+    fn fpe(&mut self) -> String {
+        let labels = &self.labels;
+        self.fpe.get_or_insert_with(|| labels.gen()).to_string()
+    }
+
+    fn instruction(&mut self, i: &ir2::Instruction) {
+        match *i {
+            ir2::Instruction::Move(d, s) => {
+                self.ins.push(Instruction::Mov(Operand::Temp(d), operand(s)));
+            }
+            ir2::Instruction::Binop(ref op, d, l, r) => self.binop(op, d, l, r),
+        }
+    }
+
+    /// Tiles `t <- x * scale` followed by `d <- a + t` with a single `leal`,
+    /// as long as nothing else refers to `t`. Returns whether it did.
+    fn scaled_add(&mut self, i: &ir2::Instruction, next: &ir2::Instruction,
+                  mentions: &HashMap<Temp, usize>) -> bool {
+        use middle::ir2::Instruction::Binop as B;
+        use middle::ir2::Operand::{Constant, Temp as T};
+
+        let (t, x, scale) = match *i {
+            B(Binop::Mul, t, T(x), Constant(c)) |
+            B(Binop::Mul, t, Constant(c), T(x)) if is_scale(c) => (t, x, c),
+            B(Binop::Shl, t, T(x), Constant(c)) if c < 4 => (t, x, 1 << c),
+            _ => return false,
+        };
+        if mentions[&t] != 2 {
+            return false
+        }
+        let (d, a) = match *next {
+            B(Binop::Add, d, T(u), a) | B(Binop::Add, d, a, T(u)) if u == t => {
+                (d, a)
+            }
+            _ => return false,
+        };
+        let (disp, base) = match a {
+            Constant(c) => (c, None),
+            T(b) => (0, Some(Operand::Temp(b))),
+        };
+        self.ins.push(Instruction::Lea(Operand::Temp(d), Address {
+            disp,
+            base,
+            index: Some(Operand::Temp(x)),
+            scale,
+        }));
+        true
     }
 
-    fn stm(&mut self, s: Statement) {
-        match s {
-            Statement::Move(Expr::Temp(t), e) =>
-                self.exp(Operand::Temp(t), e),
-            Statement::Move(..) => unreachable!(),
+    fn exit(&mut self, exit: &Exit, blocks: &[Block]) {
+        let label = |b: BlockId| blocks[b].label.to_string();
+        match *exit {
             // return e is implented as %eax <- e
-            Statement::Return(e) => {
-                self.exp(Operand::Reg(Register::EAX), e);
-                self.ins.push(Instruction::Directive("ret %eax".to_string()))
+            Exit::Return(o) => {
+                self.ins.push(Instruction::Mov(Operand::Reg(Register::Eax),
+                                               operand(o)));
+                self.ins.push(Instruction::Ret)
+            }
+            Exit::Jump(b) => self.ins.push(Instruction::Jmp(label(b))),
+            Exit::Branch(ref op, l, r, t, f) => {
+                self.ins.push(Instruction::Cmp(operand(l), operand(r)));
+                self.ins.push(Instruction::Jcc(cond(op).unwrap(), label(t)));
+                self.ins.push(Instruction::Jmp(label(f)));
             }
         }
     }
 
-    /// Generates instruction to achieve `dst <- e`
-    fn exp(&mut self, dst: Operand, e: Expr) {
-        let ins = match e {
-            Expr::Constant(c) => Instruction::Mov(dst, Operand::Imm(c)),
-            Expr::Temp(c) => Instruction::Mov(dst, Operand::Temp(c)),
-            Expr::Binop(binop, e1, e2) => {
-                let t1 = Operand::Temp(self.temps.gen());
-                let t2 = Operand::Temp(self.temps.gen());
-                self.exp(t1.clone(), *e1);
-                self.exp(t2.clone(), *e2);
-                Instruction::Binop(self.op(binop), dst, t1, t2)
+    /// Generates instructions to achieve `d <- l op r`.
+    fn binop(&mut self, binop: &Binop, d: Temp, l: ir2::Operand,
+             r: ir2::Operand) {
+        use middle::ir2::Operand::{Constant, Temp as T};
+
+        let dst = Operand::Temp(d);
+        if let Some(c) = cond(binop) {
+            self.ins.push(Instruction::Cmp(operand(l), operand(r)));
+            self.ins.push(Instruction::Setcc(c, dst));
+            return
+        }
+        if self.reduce && self.reduce_strength(binop, &dst, l, r) {
+            return
+        }
+        let lea = |disp: u32, base: Temp, index: Option<Temp>, scale: u32| {
+            Instruction::Lea(dst.clone(), Address {
+                disp,
+                base: Some(Operand::Temp(base)),
+                index: index.map(Operand::Temp),
+                scale,
+            })
+        };
+        let op = match (binop, l, r) {
+            // `-e` is translated as `0 - e`, and `~e` as `e ^ -1`
+            (&Binop::Sub, Constant(0), r) => {
+                self.ins.push(Instruction::Mov(dst.clone(), operand(r)));
+                self.ins.push(Instruction::Neg(dst));
+                return
+            }
+            (&Binop::Xor, e, Constant(0xffff_ffff)) | (&Binop::Xor, Constant(0xffff_ffff), e) => {
+                self.ins.push(Instruction::Mov(dst.clone(), operand(e)));
+                self.ins.push(Instruction::Not(dst));
+                return
+            }
+            // leal can add into a destination distinct from both operands,
+            // which saves a move
+            (&Binop::Add, T(a), T(b)) if a != d && b != d => {
+                self.ins.push(lea(0, a, Some(b), 1));
+                return
+            }
+            (&Binop::Add, T(a), Constant(c)) |
+            (&Binop::Add, Constant(c), T(a)) if a != d => {
+                self.ins.push(lea(c, a, None, 1));
+                return
             }
+            (&Binop::Sub, T(a), Constant(c)) if a != d => {
+                self.ins.push(lea(c.wrapping_neg(), a, None, 1));
+                return
+            }
+            // Multiplying by 3, 5 or 9 is `a + a * 2`, `a + a * 4` or
+            // `a + a * 8`
+            (&Binop::Mul, T(a), Constant(c)) |
+            (&Binop::Mul, Constant(c), T(a)) if is_scale(c.wrapping_sub(1)) => {
+                self.ins.push(lea(0, a, Some(a), c - 1));
+                return
+            }
+            // The shift count has to be in %cl unless it is a constant. x86
+            // only uses its low five bits, but counts outside of 0 to 31
+            // have to raise SIGFPE.
+            (&Binop::Shl, l, Constant(c)) | (&Binop::Shr, l, Constant(c))
+                if c < 32 => {
+                self.ins.push(Instruction::Mov(dst.clone(), operand(l)));
+                let op = match *binop { Binop::Shl => Op::Sal, _ => Op::Sar };
+                self.ins.push(Instruction::Binop(op, dst, Operand::Imm(c)));
+                return
+            }
+            (&Binop::Shl, l, r) | (&Binop::Shr, l, r) => {
+                let ecx = Operand::Reg(Register::Ecx);
+                let fpe = self.fpe();
+                self.ins.push(Instruction::Mov(ecx.clone(), operand(r)));
+                self.ins.push(Instruction::Cmp(ecx.clone(), Operand::Imm(31)));
+                self.ins.push(Instruction::Jcc(Cond::A, fpe));
+                self.ins.push(Instruction::Mov(dst.clone(), operand(l)));
+                let op = match *binop { Binop::Shl => Op::Sal, _ => Op::Sar };
+                self.ins.push(Instruction::Binop(op, dst, ecx));
+                return
+            }
+            // idivl divides %edx:%eax, leaving the quotient in %eax and the
+            // remainder in %edx. It raises SIGFPE by itself when dividing by
+            // zero or when the quotient overflows, as for INT_MIN / -1.
+            (&Binop::Div, l, r) | (&Binop::Mod, l, r) => {
+                let result = match *binop {
+                    Binop::Div => Register::Eax,
+                    _ => Register::Edx,
+                };
+                self.ins.push(Instruction::Mov(Operand::Reg(Register::Eax),
+                                               operand(l)));
+                self.ins.push(Instruction::Cltd);
+                self.ins.push(Instruction::Idiv(operand(r)));
+                self.ins.push(Instruction::Mov(dst, Operand::Reg(result)));
+                return
+            }
+            (&Binop::Add, _, _) => Op::Add,
+            (&Binop::Sub, _, _) => Op::Sub,
+            (&Binop::Mul, _, _) => Op::Mul,
+            (&Binop::And, _, _) => Op::And,
+            (&Binop::Or, _, _) => Op::Or,
+            (&Binop::Xor, _, _) => Op::Xor,
+            _ => unreachable!(),
         };
-        self.ins.push(ins);
+
+        // The two-address form overwrites the destination with the left
+        // operand first, so the right operand must not live there
+        let (l, r) = match (l, r) {
+            (l, T(t)) if t == d && l != r => match *binop {
+                Binop::Sub => {
+                    let copy = self.temps.gen();
+                    self.ins.push(Instruction::Mov(Operand::Temp(copy),
+                                                   Operand::Temp(t)));
+                    (l, T(copy))
+                }
+                _ => (r, l),
+            },
+            (l, r) => (l, r),
+        };
+        self.ins.push(Instruction::Mov(dst.clone(), operand(l)));
+        self.ins.push(Instruction::Binop(op, dst, operand(r)));
     }
+}
 
-    fn op(&self, op: Binop) -> Op {
-        match op {
-            Binop::Add => Op::Add,
-            Binop::Sub => Op::Sub,
-            Binop::Mul => Op::Mul,
-            Binop::Div => Op::Div,
-            Binop::Mod => Op::Mod,
+/// Counts how many times each temp is read or written.
+fn mentions(blocks: &[Block]) -> HashMap<Temp, usize> {
+    let mut mentions = HashMap::new();
+    for block in blocks.iter() {
+        let temps = block.body.iter()
+            .flat_map(|i| {
+                let mut temps = i.uses();
+                temps.push(ir2::Operand::Temp(i.def()));
+                temps
+            })
+            .chain(block.exit.uses());
+        for o in temps {
+            if let ir2::Operand::Temp(t) = o {
+                *mentions.entry(t).or_insert(0) += 1;
+            }
         }
     }
+    mentions
+}
+
+/// Checks whether `c` is a scale `leal` can multiply an index by.
+fn is_scale(c: u32) -> bool {
+    c == 1 || c == 2 || c == 4 || c == 8
+}
+
+fn operand(o: ir2::Operand) -> Operand {
+    match o {
+        ir2::Operand::Constant(c) => Operand::Imm(c),
+        ir2::Operand::Temp(t) => Operand::Temp(t),
+    }
+}
+
+/// Returns the condition tested by a comparison operator, if `op` is one.
+fn cond(op: &Binop) -> Option<Cond> {
+    match *op {
+        Binop::Lt => Some(Cond::L),
+        Binop::Le => Some(Cond::Le),
+        Binop::Gt => Some(Cond::G),
+        Binop::Ge => Some(Cond::Ge),
+        Binop::Eq => Some(Cond::E),
+        Binop::Ne => Some(Cond::Ne),
+        _ => None,
+    }
 }
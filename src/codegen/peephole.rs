@@ -0,0 +1,133 @@
+//! Peephole optimization
+//!
+//! Slides a small window over the finished instructions, after registers have
+//! been allocated and the frame laid out, and rewrites patterns which have a
+//! cheaper equivalent. Each pattern is a named rule in `RULES` which can be
+//! turned off on its own for debugging. Rules are applied until none of them
+//! matches anywhere.
+
+use codegen::asm::{Instruction, Op, Operand};
+
+/// How many instructions at the start of the window to replace, and what with.
+type Rewrite = (usize, Vec<Instruction>);
+
+pub struct Rule {
+    pub name: &'static str,
+    /// Rewrites the instructions at the start of the window, or returns
+    /// `None` if they do not match
+    rewrite: fn(&[Instruction]) -> Option<Rewrite>,
+}
+
+pub static RULES: [Rule; 4] = [
+    Rule { name: "self-move", rewrite: self_move },
+    Rule { name: "move-back", rewrite: move_back },
+    Rule { name: "jump-to-next", rewrite: jump_to_next },
+    Rule { name: "zero-xor", rewrite: zero_xor },
+];
+
+/// Returns the rules which are not named in `disabled`, where `all` turns
+/// every rule off, or the first name which is not a rule.
+pub fn rules(disabled: &[String]) -> Result<Vec<&'static Rule>, String> {
+    for name in disabled.iter() {
+        if name != "all" && !RULES.iter().any(|r| r.name == name) {
+            return Err(name.clone())
+        }
+    }
+    Ok(RULES.iter().filter(|r| {
+        !disabled.iter().any(|name| name == "all" || name == r.name)
+    }).collect())
+}
+
+/// Applies `rules` to `ins` until none of them matches.
+pub fn optimize(mut ins: Vec<Instruction>, rules: &[&Rule])
+                -> Vec<Instruction> {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut out = Vec::with_capacity(ins.len());
+        let mut i = 0;
+        while i < ins.len() {
+            match rules.iter().filter_map(|r| (r.rewrite)(&ins[i..])).next() {
+                Some((n, new)) => {
+                    out.extend(new);
+                    i += n;
+                    changed = true;
+                }
+                None => {
+                    out.push(ins[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        ins = out;
+    }
+    ins
+}
+
+/// `movl %eax, %eax` does nothing.
+fn self_move(w: &[Instruction]) -> Option<Rewrite> {
+    match w[0] {
+        Instruction::Mov(ref d, ref s) if d == s => Some((1, vec![])),
+        _ => None,
+    }
+}
+
+/// In `movl %r11d, 4(%rsp)` followed by `movl 4(%rsp), %r11d`, which is how
+/// spilled values get stored and reloaded, the second move copies back a
+/// value which is already there.
+fn move_back(w: &[Instruction]) -> Option<Rewrite> {
+    match *w {
+        [Instruction::Mov(ref d1, ref s1), Instruction::Mov(ref d2, ref s2), ..]
+            if d1 == s2 && s1 == d2 => Some((2, vec![w[0].clone()])),
+        _ => None,
+    }
+}
+
+/// A jump to a label which immediately follows it can fall through instead.
+fn jump_to_next(w: &[Instruction]) -> Option<Rewrite> {
+    let target = match w[0] {
+        Instruction::Jmp(ref l) | Instruction::Jcc(_, ref l) => l,
+        _ => return None,
+    };
+    let falls_through = w[1..].iter()
+        .take_while(|i| matches!(**i, Instruction::Label(..)))
+        .any(|i| match *i {
+            Instruction::Label(ref l) => l == target,
+            _ => false,
+        });
+    if falls_through { Some((1, vec![])) } else { None }
+}
+
+/// `xorl %eax, %eax` is shorter than `movl $0, %eax`, but it sets the flags,
+/// so it is only used when nothing reads them afterwards.
+fn zero_xor(w: &[Instruction]) -> Option<Rewrite> {
+    match w[0] {
+        Instruction::Mov(ref r @ Operand::Reg(..), Operand::Imm(0))
+            if !reads_flags(&w[1..]) => {
+            Some((1, vec![Instruction::Binop(Op::Xor, r.clone(), r.clone())]))
+        }
+        _ => None,
+    }
+}
+
+/// Checks whether the flags are read by `ins` before they are set again.
+fn reads_flags(ins: &[Instruction]) -> bool {
+    for i in ins.iter() {
+        match *i {
+            Instruction::Jcc(..) | Instruction::Setcc(..) => return true,
+            // A shift by %cl leaves the flags alone when the count is zero
+            Instruction::Binop(Op::Sal, _, _) |
+            Instruction::Binop(Op::Sar, _, _) |
+            Instruction::Binop(Op::Shr, _, _) => {}
+            Instruction::Binop(..) | Instruction::Neg(..) |
+            Instruction::Cmp(..) | Instruction::Imul(..) |
+            Instruction::Idiv(..) => return false,
+            // Comparisons are always followed by the jumps or sets reading
+            // them, so the flags are never live from one block to the next
+            Instruction::Label(..) | Instruction::Jmp(..) |
+            Instruction::Ret => return false,
+            _ => {}
+        }
+    }
+    false
+}
@@ -0,0 +1,263 @@
+//! Register allocation
+//!
+//! Builds an interference graph over the temps of a function and colors it
+//! greedily in maximum cardinality search order. Temps which cannot be given a
+//! register are assigned their own stack slot instead.
+//!
+//! Before coloring, the two sides of a move are merged into one node when they
+//! do not interfere and merging cannot make the graph harder to color, so that
+//! they end up in the same place and the move can be dropped.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use codegen::asm::{Instruction, Operand, Register};
+use codegen::liveness::{self, Liveness, Location};
+use util::Temp;
+
+/// Number of registers a temp can be colored with.
+const K: usize = 14;
+
+/// Registers available to the allocator, in order of preference.
+///
+/// Caller-saved registers come first since using them needs no extra code in
+/// the prologue and epilogue. `%r11d` is reserved as a scratch register for
+/// fixing up memory operands.
+const REGISTERS: [Register; K] = [
+    Register::Eax, Register::Ecx, Register::Edx, Register::Esi,
+    Register::Edi, Register::R8d, Register::R9d, Register::R10d,
+    Register::Ebx, Register::Ebp, Register::R12d, Register::R13d,
+    Register::R14d, Register::R15d,
+];
+
+struct Graph {
+    edges: HashMap<Location, HashSet<Location>>,
+    /// Every temp in the graph, in a fixed order to keep the output
+    /// deterministic
+    temps: Vec<Temp>,
+    /// The node each temp was merged into by coalescing
+    merged: HashMap<Temp, Location>,
+}
+
+/// Replaces every temp in `ins` with a register or a stack slot.
+pub fn allocate(ins: Vec<Instruction>) -> Vec<Instruction> {
+    let mut graph = Graph::build(&ins);
+    graph.coalesce(&ins);
+    let colors = graph.color();
+    ins.into_iter().map(|i| {
+        i.map_operands(|o| match o {
+            Operand::Temp(t) => colors[&t].clone(),
+            o => o,
+        })
+    }).filter(|i| match *i {
+        Instruction::Mov(ref d, ref s) => d != s,
+        _ => true,
+    }).collect()
+}
+
+impl Graph {
+    fn build(ins: &[Instruction]) -> Graph {
+        let mut graph = Graph {
+            edges: HashMap::new(),
+            temps: Vec::new(),
+            merged: HashMap::new(),
+        };
+
+        let live = Liveness::analyze(ins);
+        for (i, instr) in ins.iter().enumerate() {
+            for &u in live.uses(i) {
+                graph.add_node(u);
+            }
+            for &d in live.defs(i) {
+                graph.add_node(d);
+                for &l in live.live_out(i).iter() {
+                    // The source of a move may share a register with its
+                    // destination
+                    let is_src = match *instr {
+                        Instruction::Mov(_, ref s) => {
+                            liveness::location(s) == Some(l)
+                        }
+                        _ => false,
+                    };
+                    if l != d && !is_src {
+                        graph.add_node(l);
+                        graph.add_edge(d, l);
+                    }
+                }
+            }
+        }
+        graph
+    }
+
+    fn add_node(&mut self, n: Location) {
+        if self.edges.contains_key(&n) {
+            return
+        }
+        self.edges.insert(n, HashSet::new());
+        if let Location::Temp(t) = n {
+            self.temps.push(t);
+        }
+    }
+
+    fn add_edge(&mut self, a: Location, b: Location) {
+        self.edges.get_mut(&a).unwrap().insert(b);
+        self.edges.get_mut(&b).unwrap().insert(a);
+    }
+
+    /// Merges the two sides of each move which do not interfere, as long as
+    /// it is conservative.
+    ///
+    /// Two temps are merged if the result has fewer than `K` neighbors of
+    /// significant degree (Briggs), and a temp is merged into a register if
+    /// every neighbor of the temp already interferes with the register or has
+    /// insignificant degree (George). Either way, a graph which could be
+    /// colored before still can be.
+    fn coalesce(&mut self, ins: &[Instruction]) {
+        let moves = ins.iter().filter_map(|i| match *i {
+            Instruction::Mov(ref d, ref s) => {
+                match (liveness::location(d), liveness::location(s)) {
+                    (Some(d), Some(s)) => Some((d, s)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }).collect::<Vec<_>>();
+
+        // Merging can make other merges conservative, so keep going until
+        // nothing changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(d, s) in moves.iter() {
+                let (a, b) = (self.find(d), self.find(s));
+                if a == b || self.edges[&a].contains(&b) {
+                    continue
+                }
+                match (a, b) {
+                    (Location::Reg(_), Location::Reg(_)) => continue,
+                    (r @ Location::Reg(_), Location::Temp(t)) |
+                    (Location::Temp(t), r @ Location::Reg(_)) => {
+                        if !self.george(r, t) {
+                            continue
+                        }
+                        self.merge(r, t);
+                    }
+                    (a, Location::Temp(t)) => {
+                        if !self.briggs(a, Location::Temp(t)) {
+                            continue
+                        }
+                        self.merge(a, t);
+                    }
+                }
+                changed = true;
+            }
+        }
+    }
+
+    /// Returns the node `l` has been merged into.
+    fn find(&self, mut l: Location) -> Location {
+        while let Location::Temp(t) = l {
+            match self.merged.get(&t) {
+                Some(&n) => l = n,
+                None => break,
+            }
+        }
+        l
+    }
+
+    /// Checks whether `n` has as many neighbors as there are registers.
+    /// Registers count as having more.
+    fn significant(&self, n: &Location) -> bool {
+        match *n {
+            Location::Reg(..) => true,
+            Location::Temp(..) => self.edges[n].len() >= K,
+        }
+    }
+
+    fn briggs(&self, a: Location, b: Location) -> bool {
+        let neighbors = self.edges[&a].union(&self.edges[&b]);
+        neighbors.filter(|n| self.significant(n)).count() < K
+    }
+
+    fn george(&self, r: Location, t: Temp) -> bool {
+        self.edges[&Location::Temp(t)].iter().all(|n| match *n {
+            Location::Reg(..) => true,
+            Location::Temp(..) => {
+                self.edges[&r].contains(n) || !self.significant(n)
+            }
+        })
+    }
+
+    /// Merges temp `t` into node `into`, which takes over its edges.
+    fn merge(&mut self, into: Location, t: Temp) {
+        let gone = Location::Temp(t);
+        for n in self.edges.remove(&gone).unwrap() {
+            self.edges.get_mut(&n).unwrap().remove(&gone);
+            self.add_edge(into, n);
+        }
+        self.temps.retain(|&u| u != t);
+        self.merged.insert(t, into);
+    }
+
+    /// Orders temps by maximum cardinality search: each step picks the temp
+    /// with the most already-ordered neighbors.
+    fn order(&self) -> Vec<Temp> {
+        let index = self.temps.iter().enumerate().map(|(i, &t)| (t, i))
+                              .collect::<HashMap<_, _>>();
+        let mut weights = vec![0; self.temps.len()];
+        let mut done = vec![false; self.temps.len()];
+        // Entries go stale when a weight increases; ties favor earlier temps
+        let mut heap = (0..self.temps.len()).map(|i| (0, Reverse(i)))
+                                            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::new();
+        while let Some((w, Reverse(i))) = heap.pop() {
+            if done[i] || w != weights[i] {
+                continue
+            }
+            done[i] = true;
+            order.push(self.temps[i]);
+            for n in self.edges[&Location::Temp(self.temps[i])].iter() {
+                if let Location::Temp(t) = *n {
+                    let j = index[&t];
+                    if !done[j] {
+                        weights[j] += 1;
+                        heap.push((weights[j], Reverse(j)));
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    fn color(&self) -> HashMap<Temp, Operand> {
+        let mut colors = HashMap::new();
+        let mut slots = 0;
+        for t in self.order() {
+            let taken = self.edges[&Location::Temp(t)].iter().filter_map(|n| {
+                match *n {
+                    Location::Reg(r) => Some(r),
+                    Location::Temp(t) => match colors.get(&t) {
+                        Some(&Operand::Reg(r)) => Some(r),
+                        _ => None,
+                    },
+                }
+            }).collect::<HashSet<_>>();
+            let color = match REGISTERS.iter().find(|r| !taken.contains(r)) {
+                Some(&r) => Operand::Reg(r),
+                None => {
+                    slots += 1;
+                    Operand::Stack(slots - 1)
+                }
+            };
+            colors.insert(t, color);
+        }
+        for &t in self.merged.keys() {
+            let color = match self.find(Location::Temp(t)) {
+                Location::Reg(r) => Operand::Reg(r),
+                Location::Temp(u) => colors[&u].clone(),
+            };
+            colors.insert(t, color);
+        }
+        colors
+    }
+}
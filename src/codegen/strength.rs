@@ -0,0 +1,168 @@
+//! Strength reduction
+//!
+//! Multiplication by a power of two becomes a shift. Division by a power of
+//! two becomes shifts as well, and division by any other constant becomes a
+//! multiplication by a "magic number" approximating its reciprocal, as in
+//! chapter 10 of Hacker's Delight. Quotients are rounded towards zero and
+//! remainders take the sign of the dividend, as C0 requires.
+//!
+//! Dividing by 0 or -1 is left to `idivl`, which raises the arithmetic
+//! exceptions those can cause.
+
+use middle::ir::Binop;
+use middle::ir2;
+use codegen::{operand, Translator};
+use codegen::asm::{Instruction, Op, Operand, Register};
+
+impl Translator {
+    /// Generates cheaper instructions for `dst <- l op r` if `op` multiplies
+    /// or divides by a constant, returning whether it did.
+    pub fn reduce_strength(&mut self, op: &Binop, dst: &Operand,
+                           l: ir2::Operand, r: ir2::Operand) -> bool {
+        use middle::ir2::Operand::Constant;
+
+        match (op, l, r) {
+            (&Binop::Mul, e, Constant(c)) | (&Binop::Mul, Constant(c), e) => {
+                self.mul(dst, operand(e), c as i32)
+            }
+            (&Binop::Div, e, Constant(c)) | (&Binop::Mod, e, Constant(c))
+                if c != 0 && c as i32 != -1 => {
+                self.div(op, dst, operand(e), c as i32);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Generates `dst <- x * c` if `c` is 0 or a power of two, or the
+    /// negation of one, returning whether it did.
+    fn mul(&mut self, dst: &Operand, x: Operand, c: i32) -> bool {
+        let abs = c.unsigned_abs();
+        if c == 0 {
+            self.ins.push(Instruction::Mov(dst.clone(), Operand::Imm(0)));
+            return true
+        }
+        if !abs.is_power_of_two() {
+            return false
+        }
+        self.ins.push(Instruction::Mov(dst.clone(), x));
+        if abs > 1 {
+            let k = abs.trailing_zeros();
+            self.ins.push(Instruction::Binop(Op::Sal, dst.clone(),
+                                             Operand::Imm(k)));
+        }
+        if c < 0 {
+            self.ins.push(Instruction::Neg(dst.clone()));
+        }
+        true
+    }
+
+    /// Generates `dst <- x / c` or `dst <- x % c`, where `c` is neither 0
+    /// nor -1.
+    fn div(&mut self, op: &Binop, dst: &Operand, x: Operand, c: i32) {
+        let is_div = matches!(*op, Binop::Div);
+        let abs = c.unsigned_abs();
+        if abs == 1 {
+            let result = if is_div { x } else { Operand::Imm(0) };
+            self.ins.push(Instruction::Mov(dst.clone(), result));
+            return
+        }
+
+        if abs.is_power_of_two() {
+            // Shifting rounds towards negative infinity, so negative
+            // dividends are biased by `abs - 1` first, which is all ones
+            // shifted down into the bits shifted out
+            let k = abs.trailing_zeros();
+            let t = Operand::Temp(self.temps.gen());
+            self.ins.push(Instruction::Mov(t.clone(), x.clone()));
+            self.ins.push(Instruction::Binop(Op::Sar, t.clone(),
+                                             Operand::Imm(31)));
+            self.ins.push(Instruction::Binop(Op::Shr, t.clone(),
+                                             Operand::Imm(32 - k)));
+            self.ins.push(Instruction::Binop(Op::Add, t.clone(), x.clone()));
+            if is_div {
+                self.ins.push(Instruction::Binop(Op::Sar, t.clone(),
+                                                 Operand::Imm(k)));
+                if c < 0 {
+                    self.ins.push(Instruction::Neg(t.clone()));
+                }
+                self.ins.push(Instruction::Mov(dst.clone(), t));
+            } else {
+                // The remainder is what rounding the biased dividend down to
+                // a multiple of `abs` takes away
+                self.ins.push(Instruction::Binop(Op::And, t.clone(),
+                                                 Operand::Imm(abs.wrapping_neg())));
+                self.ins.push(Instruction::Mov(dst.clone(), x));
+                self.ins.push(Instruction::Binop(Op::Sub, dst.clone(), t));
+            }
+            return
+        }
+
+        // The high half of `x * magic`, shifted right, is the quotient
+        // rounded towards negative infinity
+        let (magic, shift) = magic(c);
+        let (eax, edx) = (Operand::Reg(Register::Eax),
+                          Operand::Reg(Register::Edx));
+        self.ins.push(Instruction::Mov(eax, Operand::Imm(magic as u32)));
+        self.ins.push(Instruction::Imul(x.clone()));
+        // The magic number did not fit and wrapped around
+        if c > 0 && magic < 0 {
+            self.ins.push(Instruction::Binop(Op::Add, edx.clone(), x.clone()));
+        } else if c < 0 && magic > 0 {
+            self.ins.push(Instruction::Binop(Op::Sub, edx.clone(), x.clone()));
+        }
+        if shift > 0 {
+            self.ins.push(Instruction::Binop(Op::Sar, edx.clone(),
+                                             Operand::Imm(shift)));
+        }
+        // Adding one to negative quotients rounds them towards zero
+        let q = Operand::Temp(self.temps.gen());
+        let t = Operand::Temp(self.temps.gen());
+        self.ins.push(Instruction::Mov(q.clone(), edx));
+        self.ins.push(Instruction::Mov(t.clone(), q.clone()));
+        self.ins.push(Instruction::Binop(Op::Shr, t.clone(), Operand::Imm(31)));
+        self.ins.push(Instruction::Binop(Op::Add, q.clone(), t));
+        if is_div {
+            self.ins.push(Instruction::Mov(dst.clone(), q));
+        } else {
+            self.ins.push(Instruction::Binop(Op::Mul, q.clone(),
+                                             Operand::Imm(c as u32)));
+            self.ins.push(Instruction::Mov(dst.clone(), x));
+            self.ins.push(Instruction::Binop(Op::Sub, dst.clone(), q));
+        }
+    }
+}
+
+/// Returns the magic number and shift for dividing by `d`, where `|d|` is at
+/// least 3 and not a power of two.
+fn magic(d: i32) -> (i32, u32) {
+    const TWO31: u32 = 0x8000_0000;
+    let ad = d.unsigned_abs();
+    let t = TWO31 + ((d as u32) >> 31);
+    // Absolute value of the largest dividend which leaves remainder -1
+    let anc = t - 1 - t % ad;
+    let mut p = 31;
+    let (mut q1, mut r1) = (TWO31 / anc, TWO31 % anc);
+    let (mut q2, mut r2) = (TWO31 / ad, TWO31 % ad);
+    loop {
+        p += 1;
+        q1 = q1.wrapping_mul(2);
+        r1 *= 2;
+        if r1 >= anc {
+            q1 = q1.wrapping_add(1);
+            r1 -= anc;
+        }
+        q2 = q2.wrapping_mul(2);
+        r2 *= 2;
+        if r2 >= ad {
+            q2 = q2.wrapping_add(1);
+            r2 -= ad;
+        }
+        let delta = ad - r2;
+        if q1 > delta || (q1 == delta && r1 != 0) {
+            break
+        }
+    }
+    let m = q2.wrapping_add(1) as i32;
+    (if d < 0 { m.wrapping_neg() } else { m }, p - 32)
+}
@@ -1,9 +1,5 @@
 //! L1 compiler toplevel
 
-#![feature(plugin)]
-#![plugin(rustlex)]
-#[allow(plugin_as_library)] extern crate rustlex;
-
 extern crate getopts;
 
 use std::env;
@@ -11,9 +7,12 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::io;
 use std::path::Path;
+use std::process;
 
 use getopts::Options;
 
+use util::{Code, Diagnostic, Severity};
+
 mod util;
 mod parse;
 mod types;
@@ -28,11 +27,25 @@ fn main() {
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("", "dump-ast", "print AST");
     opts.optflag("", "dump-ir", "print IR");
+    opts.optflag("", "dump-ir2", "print IR2, split into basic blocks");
+    opts.optflag("", "dump-ssa", "print the SSA form before it is optimized, at -O1 and above");
+    opts.optflag("", "dump-liveness", "print liveness of the selected instructions");
     opts.optflag("", "dump-asm", "print assembly");
+    opts.optflag("v", "verbose", "report what the optimizations removed");
     opts.optflag("t", "only-typecheck", "stop the compiler at typechecking");
+    opts.optopt("", "error-format", "how to print errors", "human|json");
+    opts.optopt("", "explain", "describe the error with the given code", "CODE");
+    opts.optmulti("", "no-peephole", "turn off a peephole rule at -O1 and \
+                                      above: self-move, move-back, \
+                                      jump-to-next, zero-xor or all",
+                  "RULE");
+    opts.optflagopt("O", "", "optimization level, 0 by default and 1 if the \
+                              level is left out", "LEVEL");
 
     let matches = opts.parse(&args[1..]).unwrap();
-    if matches.opt_present("h") || matches.free.is_empty() {
+    if let Some(code) = matches.opt_str("explain") {
+        explain(&code)
+    } else if matches.opt_present("h") || matches.free.is_empty() {
         print_usage(&program, opts)
     } else {
         compile(&matches.free[0], &matches)
@@ -44,11 +57,41 @@ fn print_usage(program: &str, opts: Options) {
     print!("{}", opts.usage(&brief))
 }
 
+fn explain(code: &str) {
+    match util::Code::from_name(code) {
+        Some(code) => println!("{}", code.explanation()),
+        None => {
+            let _ = writeln!(io::stderr(), "no error has the code `{}`", code);
+            process::exit(1)
+        }
+    }
+}
+
+/// Reports a mistake in the command line and exits.
+fn usage_error(msg: &str) -> ! {
+    let _ = writeln!(io::stderr(), "{}", msg);
+    process::exit(1)
+}
+
 /// `compile` is the main function for taking a program through the various
 /// stages of compilation.
 fn compile(input: &str, matches: &getopts::Matches) {
     let path = Path::new(input);
-    let ast = handle_error(parse::parse(path));
+    let format = match matches.opt_str("error-format") {
+        None => util::ErrorFormat::Human,
+        Some(ref s) if s == "human" => util::ErrorFormat::Human,
+        Some(ref s) if s == "json" => util::ErrorFormat::Json,
+        Some(s) => usage_error(&format!("unknown error format `{}`, \
+                                         expected `human` or `json`", s)),
+    };
+    let level = match matches.opt_default("O", "1") {
+        None => 0,
+        Some(s) => s.parse::<u32>().unwrap_or_else(|_| {
+            usage_error(&format!("unknown optimization level `{}`, expected \
+                                  a number such as `-O0` or `-O1`", s))
+        }),
+    };
+    let ast = handle_error(parse::parse(path, format));
     if matches.opt_present("dump-ast") {
         println!("{}", ast);
     }
@@ -59,20 +102,65 @@ fn compile(input: &str, matches: &getopts::Matches) {
         return;
     }
 
-    let ir = middle::translate(ast);
+    let errors = ast.errors.clone();
+    let mut ir = middle::translate(ast);
+    if level >= 1 {
+        middle::fold::fold(&mut ir);
+    }
     if matches.opt_present("dump-ir") {
         println!("{}", ir);
     }
 
-    let asm = codegen::translate(ir);
+    let mut ir2 = middle::lower::lower(ir);
+    if matches.opt_present("dump-ir2") {
+        println!("{}", ir2);
+    }
+    if level >= 1 {
+        middle::ssa::construct(&mut ir2);
+        if matches.opt_present("dump-ssa") {
+            println!("{}", ir2);
+        }
+        middle::copyprop::propagate(&mut ir2);
+        let report = middle::dce::dce(&mut ir2);
+        if matches.opt_present("verbose") {
+            errors.emit(Diagnostic::unmarked(Severity::Note,
+                                             Code::OptimizationReport,
+                                             &report.to_string()));
+        }
+        middle::ssa::destruct(&mut ir2);
+    } else if matches.opt_present("dump-ssa") {
+        errors.emit(Diagnostic::unmarked(Severity::Warning,
+                                         Code::IgnoredOption,
+                                         "`--dump-ssa` has no effect below \
+                                          -O1"));
+    }
+
+    let asm = codegen::translate(ir2, level >= 1);
+    if matches.opt_present("dump-liveness") {
+        let live = codegen::liveness::Liveness::analyze(&asm);
+        println!("{}", live.dump(&asm));
+    }
+
+    let rules = if level >= 1 {
+        codegen::peephole::rules(&matches.opt_strs("no-peephole"))
+            .unwrap_or_else(|name| {
+                let names = codegen::peephole::RULES.iter().map(|r| r.name)
+                    .collect::<Vec<_>>();
+                usage_error(&format!("unknown peephole rule `{}`, expected \
+                                      one of: all, {}", name, names.join(", ")))
+            })
+    } else {
+        Vec::new()
+    };
+    let asm = codegen::emit(asm, &rules);
     let asm = asm.into_iter().map(|x| x.to_string()).collect::<Vec<_>>();
-    let asm = asm.connect("\n");
+    let asm = asm.join("\n");
     if matches.opt_present("dump-asm") {
         println!("{}", asm);
     }
 
     handle_error(File::create(path.with_extension("s")).and_then(|mut f| {
-        f.write_all(asm.as_bytes())
+        f.write_all(asm.as_bytes()).and_then(|()| f.write_all(b"\n"))
     }));
 }
 
@@ -0,0 +1,67 @@
+//! Copy propagation
+//!
+//! Works on programs in SSA form. Neither side of a move `dst <- src` is ever
+//! assigned again, so `dst` holds the value of `src` everywhere it is used:
+//! those uses can read `src` directly and the move goes away. A phi whose
+//! arguments are all the same, apart from the phi's own result, is a copy too.
+
+use std::collections::HashMap;
+
+use middle::ir2::{Instruction, Operand, Phi, Program};
+use util::Temp;
+
+pub fn propagate(p: &mut Program) {
+    // The value each removed copy stands for
+    let mut copies = HashMap::new();
+    // Removing a copy can make a phi which used it trivial, so keep going
+    // until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in p.blocks.iter_mut() {
+            for phi in block.phis.iter_mut() {
+                for a in phi.args.iter_mut() {
+                    *a = resolve(&copies, *a);
+                }
+            }
+            block.phis.retain(|phi| match trivial(phi) {
+                Some(v) => {
+                    copies.insert(phi.dst, v);
+                    changed = true;
+                    false
+                }
+                None => true,
+            });
+            for i in block.body.iter_mut() {
+                i.map_uses(|o| resolve(&copies, o));
+            }
+            block.body.retain(|i| match *i {
+                Instruction::Move(d, s) => {
+                    copies.insert(d, s);
+                    changed = true;
+                    false
+                }
+                _ => true,
+            });
+            block.exit.map_uses(|o| resolve(&copies, o));
+        }
+    }
+}
+
+/// Follows a chain of copies back to the value it started from.
+fn resolve(copies: &HashMap<Temp, Operand>, mut o: Operand) -> Operand {
+    while let Operand::Temp(t) = o {
+        match copies.get(&t) {
+            Some(&v) => o = v,
+            None => break,
+        }
+    }
+    o
+}
+
+/// Returns the only value `phi` can choose, if there is just one.
+fn trivial(phi: &Phi) -> Option<Operand> {
+    let mut args = phi.args.iter().filter(|&&a| a != Operand::Temp(phi.dst));
+    let first = *args.next()?;
+    if args.all(|&a| a == first) { Some(first) } else { None }
+}
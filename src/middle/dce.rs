@@ -0,0 +1,167 @@
+//! Dead code elimination
+//!
+//! Works on programs in SSA form, where every temp has a single definition.
+//! Branches on two constants become jumps, and the blocks control can then no
+//! longer reach are removed. After that, an instruction or phi is only kept
+//! if its result is needed to compute a branch or the return value. Divisions
+//! and shifts which may raise an arithmetic exception are always kept, since
+//! removing them would change what the program does.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use middle::dom;
+use middle::ir::Binop;
+use middle::ir2::{BlockId, Exit, Instruction, Operand, Program};
+use util::Temp;
+
+/// What dead code elimination removed from a program.
+pub struct Report {
+    pub instructions: usize,
+    pub blocks: usize,
+}
+
+/// Where a temp is defined.
+enum Def {
+    Phi(BlockId, usize),
+    Instruction(BlockId, usize),
+}
+
+pub fn dce(p: &mut Program) -> Report {
+    let blocks = prune(p);
+    Report {
+        instructions: sweep(p),
+        blocks,
+    }
+}
+
+/// Removes the blocks control can no longer reach once branches on
+/// constants are decided, and returns how many there were.
+fn prune(p: &mut Program) -> usize {
+    for block in p.blocks.iter_mut() {
+        let target = match block.exit {
+            Exit::Branch(ref op, Operand::Constant(a), Operand::Constant(b),
+                         t, f) => if op.eval(a, b) == Some(0) { f } else { t },
+            _ => continue,
+        };
+        block.exit = Exit::Jump(target);
+    }
+
+    let mut reachable = vec![false; p.blocks.len()];
+    for b in dom::postorder(p) {
+        reachable[b] = true;
+    }
+    // Drop the edges which are gone, along with their phi arguments. Keeping
+    // the order of the remaining predecessors keeps them in step with the
+    // arguments.
+    for b in 0..p.blocks.len() {
+        let keep = p.blocks[b].preds.iter().map(|&pred| {
+            reachable[pred] && p.blocks[pred].exit.succs().contains(&b)
+        }).collect::<Vec<_>>();
+        let block = &mut p.blocks[b];
+        let mut i = 0;
+        block.preds.retain(|_| { i += 1; keep[i - 1] });
+        for phi in block.phis.iter_mut() {
+            let mut i = 0;
+            phi.args.retain(|_| { i += 1; keep[i - 1] });
+        }
+    }
+
+    let mut ids = vec![0; p.blocks.len()];
+    let mut next = 0;
+    for (b, &r) in reachable.iter().enumerate() {
+        if r {
+            ids[b] = next;
+            next += 1;
+        }
+    }
+    let removed = p.blocks.len() - next;
+    let blocks = p.blocks.drain(..).zip(reachable).filter(|&(_, r)| r)
+        .map(|(mut block, _)| {
+            block.exit = block.exit.map_blocks(|b| ids[b]);
+            for pred in block.preds.iter_mut() {
+                *pred = ids[*pred];
+            }
+            block
+        })
+        .collect();
+    p.blocks = blocks;
+    removed
+}
+
+/// Removes the instructions and phis whose results are never needed, and
+/// returns how many there were.
+fn sweep(p: &mut Program) -> usize {
+    let mut defs = HashMap::new();
+    let mut live = HashSet::new();
+    let mut work = Vec::new();
+    for (b, block) in p.blocks.iter().enumerate() {
+        for (k, phi) in block.phis.iter().enumerate() {
+            defs.insert(phi.dst, Def::Phi(b, k));
+        }
+        for (k, i) in block.body.iter().enumerate() {
+            defs.insert(i.def(), Def::Instruction(b, k));
+            if may_trap(i) {
+                for o in i.uses() {
+                    mark(o, &mut live, &mut work);
+                }
+            }
+        }
+        for o in block.exit.uses() {
+            mark(o, &mut live, &mut work);
+        }
+    }
+    while let Some(t) = work.pop() {
+        let uses = match defs[&t] {
+            Def::Phi(b, k) => p.blocks[b].phis[k].args.clone(),
+            Def::Instruction(b, k) => p.blocks[b].body[k].uses(),
+        };
+        for o in uses {
+            mark(o, &mut live, &mut work);
+        }
+    }
+
+    let mut removed = 0;
+    for block in p.blocks.iter_mut() {
+        let (phis, body) = (block.phis.len(), block.body.len());
+        block.phis.retain(|phi| live.contains(&phi.dst));
+        block.body.retain(|i| live.contains(&i.def()) || may_trap(i));
+        removed += phis - block.phis.len() + body - block.body.len();
+    }
+    removed
+}
+
+/// Marks the temp read by `o`, if any, as live.
+fn mark(o: Operand, live: &mut HashSet<Temp>, work: &mut Vec<Temp>) {
+    if let Operand::Temp(t) = o {
+        if live.insert(t) {
+            work.push(t);
+        }
+    }
+}
+
+/// Checks whether `i` may raise an arithmetic exception.
+fn may_trap(i: &Instruction) -> bool {
+    match *i {
+        Instruction::Binop(Binop::Div, _, _, r) |
+        Instruction::Binop(Binop::Mod, _, _, r) => match r {
+            Operand::Constant(c) => c == 0 || c as i32 == -1,
+            Operand::Temp(..) => true,
+        },
+        Instruction::Binop(Binop::Shl, _, _, r) |
+        Instruction::Binop(Binop::Shr, _, _, r) => match r {
+            Operand::Constant(c) => c >= 32,
+            Operand::Temp(..) => true,
+        },
+        _ => false,
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "dead code elimination removed {} instruction{} and {} \
+                   unreachable block{}",
+               self.instructions, if self.instructions == 1 { "" } else { "s" },
+               self.blocks, if self.blocks == 1 { "" } else { "s" })
+    }
+}
@@ -0,0 +1,118 @@
+//! Dominators
+//!
+//! Block `a` dominates block `b` if every path from the entry to `b` passes
+//! through `a`. The immediate dominators are found with the iterative
+//! algorithm of Cooper, Harvey and Kennedy, which visits blocks in reverse
+//! postorder until nothing changes. Every block has to be reachable from the
+//! entry.
+
+use middle::ir2::{BlockId, Program, ENTRY};
+
+pub struct Dominators {
+    /// Children of each block in the dominator tree
+    children: Vec<Vec<BlockId>>,
+    /// Blocks where the dominance of each block ends
+    frontiers: Vec<Vec<BlockId>>,
+}
+
+impl Dominators {
+    pub fn compute(p: &Program) -> Dominators {
+        let order = postorder(p);
+        let mut number = vec![0; p.blocks.len()];
+        for (i, &b) in order.iter().enumerate() {
+            number[b] = i;
+        }
+
+        // Walks up from two blocks with known dominators until they meet
+        let intersect = |idom: &[Option<BlockId>], mut a: BlockId,
+                         mut b: BlockId| {
+            while a != b {
+                while number[a] < number[b] { a = idom[a].unwrap(); }
+                while number[b] < number[a] { b = idom[b].unwrap(); }
+            }
+            a
+        };
+        let mut idom = vec![None; p.blocks.len()];
+        idom[ENTRY] = Some(ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in order.iter().rev().filter(|&&b| b != ENTRY) {
+                let mut preds = p.blocks[b].preds.iter()
+                    .filter(|&&pred| idom[pred].is_some());
+                let first = *preds.next().unwrap();
+                let new = preds.fold(first, |d, &pred| {
+                    intersect(&idom, d, pred)
+                });
+                if idom[b] != Some(new) {
+                    idom[b] = Some(new);
+                    changed = true;
+                }
+            }
+        }
+        let idom = idom.into_iter().map(Option::unwrap).collect::<Vec<_>>();
+
+        let mut children = vec![Vec::new(); p.blocks.len()];
+        for (b, &d) in idom.iter().enumerate().filter(|&(b, _)| b != ENTRY) {
+            children[d].push(b);
+        }
+
+        // A join point is in the frontier of each block from its
+        // predecessors up to, but not including, its immediate dominator
+        let mut frontiers = vec![Vec::new(); p.blocks.len()];
+        for (b, block) in p.blocks.iter().enumerate() {
+            if block.preds.len() < 2 {
+                continue
+            }
+            for &pred in block.preds.iter() {
+                let mut runner = pred;
+                while runner != idom[b] {
+                    if !frontiers[runner].contains(&b) {
+                        frontiers[runner].push(b);
+                    }
+                    runner = idom[runner];
+                }
+            }
+        }
+
+        Dominators {
+            children,
+            frontiers,
+        }
+    }
+
+    /// Returns the blocks immediately dominated by `b`.
+    pub fn children(&self, b: BlockId) -> &[BlockId] {
+        &self.children[b]
+    }
+
+    /// Returns the dominance frontier of `b`: the blocks which `b` does not
+    /// strictly dominate, but which have a predecessor that `b` dominates.
+    pub fn frontier(&self, b: BlockId) -> &[BlockId] {
+        &self.frontiers[b]
+    }
+}
+
+/// Returns the blocks of `p` in postorder of a depth-first search from the
+/// entry.
+pub fn postorder(p: &Program) -> Vec<BlockId> {
+    let mut order = Vec::new();
+    let mut visited = vec![false; p.blocks.len()];
+    // Each entry is a block along with the number of its successors visited
+    let mut stack = vec![(ENTRY, 0)];
+    visited[ENTRY] = true;
+    while let Some(&mut (b, ref mut next)) = stack.last_mut() {
+        let succs = p.blocks[b].exit.succs();
+        if let Some(&s) = succs.get(*next) {
+            *next += 1;
+            if !visited[s] {
+                visited[s] = true;
+                stack.push((s, 0));
+            }
+        } else {
+            order.push(b);
+            stack.pop();
+        }
+    }
+    order
+}
@@ -0,0 +1,39 @@
+//! Constant folding
+//!
+//! Evaluates the parts of expressions whose operands are all constants, and
+//! turns conditional jumps on constants into plain jumps. Operations which
+//! would raise an arithmetic exception are left for the program to perform at
+//! runtime.
+
+use middle::ir::{Expr, Program, Statement};
+
+pub fn fold(p: &mut Program) {
+    for s in p.statements.iter_mut() {
+        let folded = match *s {
+            Statement::Move(ref d, ref e) => Statement::Move(d.clone(), exp(e)),
+            Statement::Return(ref e) => Statement::Return(exp(e)),
+            Statement::CJump(ref e, t, f) => match exp(e) {
+                Expr::Constant(0) => Statement::Jump(f),
+                Expr::Constant(_) => Statement::Jump(t),
+                e => Statement::CJump(e, t, f),
+            },
+            Statement::Label(..) | Statement::Jump(..) => continue,
+        };
+        *s = folded;
+    }
+}
+
+fn exp(e: &Expr) -> Expr {
+    match *e {
+        Expr::Binop(ref op, ref e1, ref e2) => {
+            let (e1, e2) = (exp(e1), exp(e2));
+            if let (&Expr::Constant(a), &Expr::Constant(b)) = (&e1, &e2) {
+                if let Some(c) = op.eval(a, b) {
+                    return Expr::Constant(c)
+                }
+            }
+            Expr::Binop(op.clone(), Box::new(e1), Box::new(e2))
+        }
+        ref e => e.clone(),
+    }
+}
@@ -2,17 +2,23 @@
 
 use std::fmt;
 
-use util::{Temp, TempAllocator};
+use util::{Label, LabelAllocator, Temp, TempAllocator};
 
 pub struct Program {
     pub statements: Vec<Statement>,
     pub temps: TempAllocator,
+    pub labels: LabelAllocator,
 }
 
 #[derive(Clone)]
 pub enum Statement {
     Move(Expr, Expr),
     Return(Expr),
+    Label(Label),
+    Jump(Label),
+    /// Jumps to the first label if the expression is nonzero, and to the
+    /// second otherwise
+    CJump(Expr, Label, Label),
 }
 
 #[derive(Clone)]
@@ -23,13 +29,54 @@ pub enum Expr {
 }
 
 #[derive(Clone)]
-pub enum Binop { Add, Sub, Mul, Div, Mod }
+pub enum Binop {
+    Add, Sub, Mul, Div, Mod,
+    And, Or, Xor,
+    /// Shifts, where `Shr` is arithmetic and so copies the sign bit
+    Shl, Shr,
+    /// Comparisons, which evaluate to 1 if they hold and 0 otherwise
+    Lt, Le, Gt, Ge, Eq, Ne,
+}
+
+impl Binop {
+    /// Returns whether the operator is a comparison.
+    pub fn is_comparison(&self) -> bool {
+        matches!(*self, Binop::Lt | Binop::Le | Binop::Gt | Binop::Ge |
+                        Binop::Eq | Binop::Ne)
+    }
+
+    /// Evaluates the operator on 32-bit two's complement integers, or returns
+    /// `None` if doing so raises an arithmetic exception at runtime.
+    pub fn eval(&self, a: u32, b: u32) -> Option<u32> {
+        let (x, y) = (a as i32, b as i32);
+        let result = match *self {
+            Binop::Add => x.wrapping_add(y),
+            Binop::Sub => x.wrapping_sub(y),
+            Binop::Mul => x.wrapping_mul(y),
+            Binop::Div => x.checked_div(y)?,
+            Binop::Mod => x.checked_rem(y)?,
+            Binop::And => x & y,
+            Binop::Or => x | y,
+            Binop::Xor => x ^ y,
+            Binop::Shl if b < 32 => x << y,
+            Binop::Shr if b < 32 => x >> y,
+            Binop::Shl | Binop::Shr => return None,
+            Binop::Lt => (x < y) as i32,
+            Binop::Le => (x <= y) as i32,
+            Binop::Gt => (x > y) as i32,
+            Binop::Ge => (x >= y) as i32,
+            Binop::Eq => (x == y) as i32,
+            Binop::Ne => (x != y) as i32,
+        };
+        Some(result as u32)
+    }
+}
 
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for (i, statement) in self.statements.iter().enumerate() {
-            if i > 0 { try!(write!(f, "\n")) }
-            try!(write!(f, "{}", statement))
+            if i > 0 { writeln!(f)? }
+            write!(f, "{}", statement)?
         }
         Ok(())
     }
@@ -40,6 +87,11 @@ impl fmt::Display for Statement {
         match *self {
             Statement::Move(ref e1, ref e2) => write!(f, "{} <-- {}", e1, e2),
             Statement::Return(ref e) => write!(f, "return {}", e),
+            Statement::Label(ref l) => write!(f, "{}:", l),
+            Statement::Jump(ref l) => write!(f, "goto {}", l),
+            Statement::CJump(ref e, ref l1, ref l2) => {
+                write!(f, "if {} goto {} else goto {}", e, l1, l2)
+            }
         }
     }
 }
@@ -64,6 +116,17 @@ impl fmt::Display for Binop {
             Binop::Mul => "*".fmt(f),
             Binop::Div => "/".fmt(f),
             Binop::Mod => "%".fmt(f),
+            Binop::And => "&".fmt(f),
+            Binop::Or => "|".fmt(f),
+            Binop::Xor => "^".fmt(f),
+            Binop::Shl => "<<".fmt(f),
+            Binop::Shr => ">>".fmt(f),
+            Binop::Lt => "<".fmt(f),
+            Binop::Le => "<=".fmt(f),
+            Binop::Gt => ">".fmt(f),
+            Binop::Ge => ">=".fmt(f),
+            Binop::Eq => "==".fmt(f),
+            Binop::Ne => "!=".fmt(f),
         }
     }
 }
@@ -0,0 +1,217 @@
+//! IR2, a three-address intermediate representation
+//!
+//! Every instruction applies at most one operator to temps and constants, and
+//! instructions are grouped into basic blocks which end in a single jump,
+//! branch or return. The blocks form the control-flow graph of the function.
+
+use std::fmt;
+
+use middle::ir::Binop;
+use util::{Label, LabelAllocator, Temp, TempAllocator};
+
+/// Index of a block in `Program::blocks`.
+pub type BlockId = usize;
+
+/// The entry block of every program.
+pub const ENTRY: BlockId = 0;
+
+pub struct Program {
+    pub blocks: Vec<Block>,
+    pub temps: TempAllocator,
+    pub labels: LabelAllocator,
+}
+
+pub struct Block {
+    pub label: Label,
+    /// Phi functions, which are only present in SSA form
+    pub phis: Vec<Phi>,
+    pub body: Vec<Instruction>,
+    pub exit: Exit,
+    /// Blocks which exit to this one, in order
+    pub preds: Vec<BlockId>,
+}
+
+/// `dst <- phi(args)`, which takes the argument for the predecessor control
+/// came from. The arguments are in the same order as the block's
+/// predecessors, so they have to be kept in step when edges change.
+#[derive(Clone)]
+pub struct Phi {
+    pub dst: Temp,
+    pub args: Vec<Operand>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Constant(u32),
+    Temp(Temp),
+}
+
+#[derive(Clone)]
+pub enum Instruction {
+    /// `dst <- src`
+    Move(Temp, Operand),
+    /// `dst <- lhs op rhs`
+    Binop(Binop, Temp, Operand, Operand),
+}
+
+#[derive(Clone)]
+pub enum Exit {
+    Return(Operand),
+    Jump(BlockId),
+    /// Goes to the first block if the comparison `lhs op rhs` holds, and to
+    /// the second otherwise
+    Branch(Binop, Operand, Operand, BlockId, BlockId),
+}
+
+impl Program {
+    /// Recomputes the predecessors of every block from their exits.
+    pub fn link(&mut self) {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.exit.succs() {
+                if !preds[succ].contains(&id) {
+                    preds[succ].push(id);
+                }
+            }
+        }
+        for (block, preds) in self.blocks.iter_mut().zip(preds) {
+            block.preds = preds;
+        }
+    }
+}
+
+impl Instruction {
+    /// Returns the temp the instruction writes.
+    pub fn def(&self) -> Temp {
+        match *self {
+            Instruction::Move(d, _) | Instruction::Binop(_, d, _, _) => d,
+        }
+    }
+
+    /// Returns the operands the instruction reads.
+    pub fn uses(&self) -> Vec<Operand> {
+        match *self {
+            Instruction::Move(_, s) => vec![s],
+            Instruction::Binop(_, _, l, r) => vec![l, r],
+        }
+    }
+
+    /// Replaces the temp the instruction writes with `t`.
+    pub fn set_def(&mut self, t: Temp) {
+        match *self {
+            Instruction::Move(ref mut d, _) |
+            Instruction::Binop(_, ref mut d, _, _) => *d = t,
+        }
+    }
+
+    /// Rewrites every operand the instruction reads with `f`.
+    pub fn map_uses<F: FnMut(Operand) -> Operand>(&mut self, mut f: F) {
+        match *self {
+            Instruction::Move(_, ref mut s) => *s = f(*s),
+            Instruction::Binop(_, _, ref mut l, ref mut r) => {
+                *l = f(*l);
+                *r = f(*r);
+            }
+        }
+    }
+}
+
+impl Exit {
+    /// Returns the operands the exit reads.
+    pub fn uses(&self) -> Vec<Operand> {
+        match *self {
+            Exit::Return(o) => vec![o],
+            Exit::Jump(..) => vec![],
+            Exit::Branch(_, l, r, _, _) => vec![l, r],
+        }
+    }
+
+    /// Rewrites every operand the exit reads with `f`.
+    pub fn map_uses<F: FnMut(Operand) -> Operand>(&mut self, mut f: F) {
+        match *self {
+            Exit::Return(ref mut o) => *o = f(*o),
+            Exit::Jump(..) => {}
+            Exit::Branch(_, ref mut l, ref mut r, _, _) => {
+                *l = f(*l);
+                *r = f(*r);
+            }
+        }
+    }
+
+    /// Renames every block the exit goes to with `f`.
+    pub fn map_blocks<F: Fn(BlockId) -> BlockId>(self, f: F) -> Exit {
+        match self {
+            Exit::Return(o) => Exit::Return(o),
+            Exit::Jump(b) => Exit::Jump(f(b)),
+            Exit::Branch(op, l, r, t, e) => Exit::Branch(op, l, r, f(t), f(e)),
+        }
+    }
+
+    /// Returns the blocks control may go to next.
+    pub fn succs(&self) -> Vec<BlockId> {
+        match *self {
+            Exit::Return(..) => vec![],
+            Exit::Jump(b) => vec![b],
+            Exit::Branch(_, _, _, t, f) if t == f => vec![t],
+            Exit::Branch(_, _, _, t, f) => vec![t, f],
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = |b: &BlockId| self.blocks[*b].label.to_string();
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i > 0 { writeln!(f)? }
+            write!(f, "{}:", block.label)?;
+            if !block.preds.is_empty() {
+                let preds = block.preds.iter().map(&label).collect::<Vec<_>>();
+                write!(f, "\t\t// preds: {}", preds.join(", "))?;
+            }
+            writeln!(f)?;
+            for phi in block.phis.iter() {
+                writeln!(f, "\t{}", phi)?;
+            }
+            for ins in block.body.iter() {
+                writeln!(f, "\t{}", ins)?;
+            }
+            match block.exit {
+                Exit::Return(ref o) => write!(f, "\treturn {}", o)?,
+                Exit::Jump(ref b) => write!(f, "\tgoto {}", label(b))?,
+                Exit::Branch(ref op, ref l, ref r, ref t, ref e) => {
+                    write!(f, "\tif {} {} {} goto {} else goto {}", l, op, r,
+                           label(t), label(e))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Move(ref d, ref s) => write!(f, "{} <-- {}", d, s),
+            Instruction::Binop(ref op, ref d, ref l, ref r) => {
+                write!(f, "{} <-- {} {} {}", d, l, op, r)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Phi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let args = self.args.iter().map(|a| a.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{} <-- phi({})", self.dst, args.join(", "))
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Constant(c) => write!(f, "{}", c),
+            Operand::Temp(ref t) => write!(f, "{}", t),
+        }
+    }
+}
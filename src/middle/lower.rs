@@ -0,0 +1,165 @@
+//! Lowering from the tree IR to IR2
+//!
+//! Splits the statements of the program into basic blocks at labels, jumps and
+//! returns, and flattens expressions into three-address instructions which
+//! hold intermediate results in fresh temps. Code which control can never
+//! reach is dropped along the way.
+
+use std::collections::HashMap;
+
+use middle::ir::{self, Binop, Expr, Statement};
+use middle::ir2::{Block, BlockId, Exit, Instruction, Operand, Program, ENTRY};
+use util::{Label, TempAllocator};
+
+/// A block which may not have reached its exit yet.
+struct Partial {
+    label: Label,
+    body: Vec<Instruction>,
+    exit: Option<Exit>,
+}
+
+struct Builder {
+    blocks: Vec<Partial>,
+    /// The block starting at each label seen so far
+    ids: HashMap<Label, BlockId>,
+    /// The block being added to, or `None` in the unreachable code after a
+    /// jump or return
+    current: Option<BlockId>,
+    temps: TempAllocator,
+}
+
+pub fn lower(p: ir::Program) -> Program {
+    let ir::Program { statements, temps, labels } = p;
+    let mut builder = Builder {
+        blocks: Vec::new(),
+        ids: HashMap::new(),
+        current: None,
+        temps,
+    };
+    let entry = builder.block(labels.gen());
+    builder.current = Some(entry);
+    for s in statements.into_iter() {
+        builder.stm(s);
+    }
+
+    let mut p = Program {
+        blocks: builder.finish(),
+        temps: builder.temps,
+        labels,
+    };
+    p.link();
+    p
+}
+
+impl Builder {
+    /// Returns the block starting at `l`, creating it if it is new.
+    fn block(&mut self, l: Label) -> BlockId {
+        if let Some(&id) = self.ids.get(&l) {
+            return id
+        }
+        self.blocks.push(Partial { label: l, body: Vec::new(), exit: None });
+        self.ids.insert(l, self.blocks.len() - 1);
+        self.blocks.len() - 1
+    }
+
+    /// Ends the current block, if control can reach it, with `exit`.
+    fn exit(&mut self, exit: Exit) {
+        if let Some(id) = self.current.take() {
+            self.blocks[id].exit = Some(exit);
+        }
+    }
+
+    fn push(&mut self, i: Instruction) {
+        if let Some(id) = self.current {
+            self.blocks[id].body.push(i);
+        }
+    }
+
+    fn stm(&mut self, s: Statement) {
+        match s {
+            // The previous block falls through into the new one
+            Statement::Label(l) => {
+                let id = self.block(l);
+                self.exit(Exit::Jump(id));
+                self.current = Some(id);
+            }
+            Statement::Jump(l) => {
+                let id = self.block(l);
+                self.exit(Exit::Jump(id));
+            }
+            Statement::CJump(e, t, f) => {
+                let (t, f) = (self.block(t), self.block(f));
+                let (op, l, r) = match e {
+                    Expr::Binop(op, e1, e2) if op.is_comparison() => {
+                        (op, self.operand(*e1), self.operand(*e2))
+                    }
+                    e => (Binop::Ne, self.operand(e), Operand::Constant(0)),
+                };
+                self.exit(Exit::Branch(op, l, r, t, f));
+            }
+            Statement::Return(e) => {
+                let o = self.operand(e);
+                self.exit(Exit::Return(o));
+            }
+            Statement::Move(Expr::Temp(d), Expr::Binop(op, e1, e2)) => {
+                let (l, r) = (self.operand(*e1), self.operand(*e2));
+                self.push(Instruction::Binop(op, d, l, r));
+            }
+            Statement::Move(Expr::Temp(d), e) => {
+                let o = self.operand(e);
+                self.push(Instruction::Move(d, o));
+            }
+            Statement::Move(..) => unreachable!(),
+        }
+    }
+
+    /// Flattens `e`, returning the operand which holds its value.
+    fn operand(&mut self, e: Expr) -> Operand {
+        match e {
+            Expr::Constant(c) => Operand::Constant(c),
+            Expr::Temp(t) => Operand::Temp(t),
+            Expr::Binop(op, e1, e2) => {
+                let (l, r) = (self.operand(*e1), self.operand(*e2));
+                let t = self.temps.gen();
+                self.push(Instruction::Binop(op, t, l, r));
+                Operand::Temp(t)
+            }
+        }
+    }
+
+    /// Drops the blocks control never reaches and numbers the rest in order.
+    ///
+    /// Every reachable block has an exit, since the typechecker makes sure
+    /// every path through the program returns.
+    fn finish(&mut self) -> Vec<Block> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![ENTRY];
+        while let Some(id) = stack.pop() {
+            if reachable[id] {
+                continue
+            }
+            reachable[id] = true;
+            let exit = self.blocks[id].exit.as_ref()
+                .expect("control reaches the end of main");
+            stack.extend(exit.succs());
+        }
+
+        let mut ids = vec![0; self.blocks.len()];
+        let mut next = 0;
+        for (id, &r) in reachable.iter().enumerate() {
+            if r {
+                ids[id] = next;
+                next += 1;
+            }
+        }
+        self.blocks.drain(..).zip(reachable).filter(|&(_, r)| r)
+            .map(|(b, _)| Block {
+                label: b.label,
+                phis: Vec::new(),
+                body: b.body,
+                exit: b.exit.unwrap().map_blocks(|id| ids[id]),
+                preds: Vec::new(),
+            })
+            .collect()
+    }
+}
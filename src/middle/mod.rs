@@ -4,32 +4,34 @@ use std::collections::HashMap;
 
 use middle::ir::{Binop, Statement, Expr};
 use parse::ast::{self, Expr_, Operator, Statement_};
-use util::{Temp, TempAllocator};
+use util::{Label, LabelAllocator, Temp, TempAllocator};
 
 pub mod ir;
+pub mod ir2;
+pub mod fold;
+pub mod lower;
+pub mod dom;
+pub mod ssa;
+pub mod dce;
+pub mod copyprop;
 
 struct Translator {
     temps: TempAllocator,
+    labels: LabelAllocator,
     syms: HashMap<ast::Ident, Temp>,
+    stmts: Vec<Statement>,
+    /// The (continue, break) targets of each enclosing loop, innermost last
+    loops: Vec<(Label, Label)>,
 }
 
 pub fn translate(p: ast::Program) -> ir::Program {
     let mut translator = Translator::new();
-    let mut stmts = vec![];
-
-    for stm in p.statements {
-        match translator.stm(&stm) {
-            Some(new_stm) => {
-                stmts.push(new_stm.clone());
-                if let Statement::Return(..) = new_stm { break };
-            },
-            None => {}
-        }
-    }
+    translator.block(&p.statements);
 
     ir::Program {
-        statements: stmts,
+        statements: translator.stmts,
         temps: translator.temps,
+        labels: translator.labels,
     }
 }
 
@@ -37,19 +39,150 @@ impl Translator {
     fn new() -> Translator {
         Translator {
             temps: TempAllocator::new(),
+            labels: LabelAllocator::new(),
             syms: HashMap::new(),
+            stmts: Vec::new(),
+            loops: Vec::new(),
         }
     }
 
-    fn stm(&mut self, stm: &ast::Statement) -> Option<Statement> {
+    /// Translates a sequence of statements in their own scope, stopping at the
+    /// first `return` since anything after it is unreachable.
+    fn block(&mut self, stms: &[ast::Statement]) {
+        let outer = self.syms.clone();
+        for stm in stms {
+            self.stm(stm);
+            if let Statement_::Return(..) = stm.node { break }
+        }
+        self.syms = outer;
+    }
+
+    /// Translates a single statement in its own scope.
+    fn scoped(&mut self, stm: &ast::Statement) {
+        let outer = self.syms.clone();
+        self.stm(stm);
+        self.syms = outer;
+    }
+
+    fn stm(&mut self, stm: &ast::Statement) {
         match stm.node {
-            Statement_::Assign(id, ref e) |
-            Statement_::DeclAssign(id, ref e) => {
+            Statement_::DeclAssign(_, id, ref e) => {
                 let exp = self.exp(e);
-                Some(Statement::Move(Expr::Temp(self.temp(id)), exp))
+                let temp = self.temp(id);
+                self.stmts.push(Statement::Move(Expr::Temp(temp), exp));
+            }
+            Statement_::Assign(id, ref e) => {
+                let exp = self.exp(e);
+                let temp = self.syms[&id];
+                self.stmts.push(Statement::Move(Expr::Temp(temp), exp));
+            }
+            Statement_::Postop(id, op) => {
+                let temp = Expr::Temp(self.syms[&id]);
+                let exp = Expr::Binop(self.op(op),
+                                      Box::new(temp.clone()),
+                                      Box::new(Expr::Constant(1)));
+                self.stmts.push(Statement::Move(temp, exp));
+            }
+            Statement_::Return(ref e) => {
+                let exp = self.exp(e);
+                self.stmts.push(Statement::Return(exp));
+            }
+            Statement_::Decl(_, id) => { self.temp(id); }
+            Statement_::Block(ref stms) => self.block(stms),
+            Statement_::If(ref e, ref s1, ref s2) => {
+                let (then, els, done) =
+                    (self.labels.gen(), self.labels.gen(), self.labels.gen());
+                self.cond(e, then, els);
+                self.stmts.push(Statement::Label(then));
+                self.scoped(s1);
+                self.stmts.push(Statement::Jump(done));
+                self.stmts.push(Statement::Label(els));
+                if let Some(ref s2) = *s2 {
+                    self.scoped(s2);
+                }
+                self.stmts.push(Statement::Label(done));
+            }
+            Statement_::While(ref e, ref body) => {
+                let cond = self.labels.gen();
+                self.stmts.push(Statement::Label(cond));
+                self.lp(e, body, None, cond);
+            }
+            Statement_::For(ref init, ref e, ref step, ref body) => {
+                let outer = self.syms.clone();
+                if let Some(ref init) = *init {
+                    self.stm(init);
+                }
+                let cond = self.labels.gen();
+                self.stmts.push(Statement::Label(cond));
+                self.lp(e, body, step.as_ref().map(|s| &**s), cond);
+                self.syms = outer;
+            }
+            Statement_::Break => {
+                let (_, done) = *self.loops.last().unwrap();
+                self.stmts.push(Statement::Jump(done));
+            }
+            Statement_::Continue => {
+                let (next, _) = *self.loops.last().unwrap();
+                self.stmts.push(Statement::Jump(next));
+            }
+            // Programs with syntax errors are never translated
+            Statement_::Error => unreachable!(),
+        }
+    }
+
+    /// Translates the rest of a loop whose condition check begins at label
+    /// `cond`, running `step` (if any) after each iteration of `body`.
+    fn lp(&mut self, e: &ast::Expr, body: &ast::Statement,
+          step: Option<&ast::Statement>, cond: Label) {
+        let (start, next, done) =
+            (self.labels.gen(), self.labels.gen(), self.labels.gen());
+        self.cond(e, start, done);
+        self.stmts.push(Statement::Label(start));
+        self.loops.push((next, done));
+        self.scoped(body);
+        self.loops.pop();
+        self.stmts.push(Statement::Label(next));
+        if let Some(step) = step {
+            self.stm(step);
+        }
+        self.stmts.push(Statement::Jump(cond));
+        self.stmts.push(Statement::Label(done));
+    }
+
+    /// Translates the boolean expression `e` into a jump to `t` if it holds
+    /// and to `f` otherwise.
+    ///
+    /// The right operand of `&&` and `||` is only evaluated when the left
+    /// operand does not already decide the result.
+    fn cond(&mut self, e: &ast::Expr, t: Label, f: Label) {
+        match e.node {
+            Expr_::Bool(true) => self.stmts.push(Statement::Jump(t)),
+            Expr_::Bool(false) => self.stmts.push(Statement::Jump(f)),
+            Expr_::Unary(Operator::Not, ref e) => self.cond(e, f, t),
+            Expr_::Binary(Operator::And, ref e1, ref e2) => {
+                let rhs = self.labels.gen();
+                self.cond(e1, rhs, f);
+                self.stmts.push(Statement::Label(rhs));
+                self.cond(e2, t, f);
+            }
+            Expr_::Binary(Operator::Or, ref e1, ref e2) => {
+                let rhs = self.labels.gen();
+                self.cond(e1, t, rhs);
+                self.stmts.push(Statement::Label(rhs));
+                self.cond(e2, t, f);
+            }
+            Expr_::Ternary(ref c, ref e1, ref e2) => {
+                let (l1, l2) = (self.labels.gen(), self.labels.gen());
+                self.cond(c, l1, l2);
+                self.stmts.push(Statement::Label(l1));
+                self.cond(e1, t, f);
+                self.stmts.push(Statement::Label(l2));
+                self.cond(e2, t, f);
+            }
+            _ => {
+                let exp = self.exp(e);
+                self.stmts.push(Statement::CJump(exp, t, f));
             }
-            Statement_::Return(ref e) => Some(Statement::Return(self.exp(e))),
-            Statement_::Decl(_) => None
         }
     }
 
@@ -59,11 +192,50 @@ impl Translator {
                 Expr::Temp(*self.syms.get(&id).unwrap())
             },
             Expr_::Constant(c) => Expr::Constant(c),
+            Expr_::Bool(b) => Expr::Constant(b as u32),
+            // Logical operators need control flow, so their result is
+            // computed into a fresh temp ahead of the enclosing expression
+            Expr_::Unary(Operator::Not, _) |
+            Expr_::Binary(Operator::And, _, _) |
+            Expr_::Binary(Operator::Or, _, _) => {
+                let temp = Expr::Temp(self.temps.gen());
+                let (t, f, done) =
+                    (self.labels.gen(), self.labels.gen(), self.labels.gen());
+                self.cond(exp, t, f);
+                self.stmts.push(Statement::Label(t));
+                self.stmts.push(Statement::Move(temp.clone(), Expr::Constant(1)));
+                self.stmts.push(Statement::Jump(done));
+                self.stmts.push(Statement::Label(f));
+                self.stmts.push(Statement::Move(temp.clone(), Expr::Constant(0)));
+                self.stmts.push(Statement::Label(done));
+                temp
+            }
+            // Only the chosen branch is evaluated
+            Expr_::Ternary(ref c, ref e1, ref e2) => {
+                let temp = Expr::Temp(self.temps.gen());
+                let (t, f, done) =
+                    (self.labels.gen(), self.labels.gen(), self.labels.gen());
+                self.cond(c, t, f);
+                self.stmts.push(Statement::Label(t));
+                let exp = self.exp(e1);
+                self.stmts.push(Statement::Move(temp.clone(), exp));
+                self.stmts.push(Statement::Jump(done));
+                self.stmts.push(Statement::Label(f));
+                let exp = self.exp(e2);
+                self.stmts.push(Statement::Move(temp.clone(), exp));
+                self.stmts.push(Statement::Label(done));
+                temp
+            }
             Expr_::Unary(Operator::Negative, ref e) => {
                 Expr::Binop(Binop::Sub,
                             Box::new(Expr::Constant(0)),
                             Box::new(self.exp(e)))
             }
+            Expr_::Unary(Operator::BitNot, ref e) => {
+                Expr::Binop(Binop::Xor,
+                            Box::new(self.exp(e)),
+                            Box::new(Expr::Constant(!0)))
+            }
             Expr_::Binary(op, ref e1, ref e2) =>
                 Expr::Binop(self.op(op),
                             Box::new(self.exp(e1)),
@@ -86,8 +258,22 @@ impl Translator {
             Operator::Times => Binop::Mul,
             Operator::DividedBy => Binop::Div,
             Operator::Modulo => Binop::Mod,
+            Operator::Less => Binop::Lt,
+            Operator::LessEq => Binop::Le,
+            Operator::Greater => Binop::Gt,
+            Operator::GreaterEq => Binop::Ge,
+            Operator::Equal => Binop::Eq,
+            Operator::NotEqual => Binop::Ne,
+            Operator::BitAnd => Binop::And,
+            Operator::BitOr => Binop::Or,
+            Operator::BitXor => Binop::Xor,
+            Operator::ShiftLeft => Binop::Shl,
+            Operator::ShiftRight => Binop::Shr,
             Operator::Negative => Binop::Sub, // unary to binary!
-            Operator::Decrement => unreachable!(),
+            Operator::Increment => Binop::Add,
+            Operator::Decrement => Binop::Sub,
+            Operator::And | Operator::Or | Operator::Not |
+            Operator::BitNot => unreachable!(),
         }
     }
 }
@@ -0,0 +1,230 @@
+//! Static single assignment form
+//!
+//! In SSA form every temp is written by exactly one instruction or phi, and
+//! the phis at the start of a block choose between the values of a variable
+//! arriving along each incoming edge. Phis are only placed where the variable
+//! is live, which gives pruned SSA.
+//!
+//! Leaving SSA form replaces each phi by copies at the end of its block's
+//! predecessors. An edge from a block with several successors is split first,
+//! so that the copies only run when control actually goes to the phi.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+
+use middle::dom::{self, Dominators};
+use middle::ir2::{Block, BlockId, Exit, Instruction, Operand, Phi, Program,
+                  ENTRY};
+use util::{Temp, TempAllocator};
+
+/// A step of the walk over the dominator tree while renaming.
+enum Step {
+    Enter(BlockId),
+    /// Pops the stack of each variable given a new name in a block
+    Leave(Vec<Temp>),
+}
+
+/// Converts `p` into SSA form.
+pub fn construct(p: &mut Program) {
+    let dom = Dominators::compute(p);
+    let live = live_in(p);
+
+    // A variable needs a phi where the values from two of its definitions
+    // can first meet, which is the iterated dominance frontier of the blocks
+    // defining it
+    let mut defs = BTreeMap::new();
+    for (b, block) in p.blocks.iter().enumerate() {
+        for i in block.body.iter() {
+            let blocks = defs.entry(i.def()).or_insert_with(Vec::new);
+            if blocks.last() != Some(&b) {
+                blocks.push(b);
+            }
+        }
+    }
+    // The variable each phi was placed for
+    let mut vars = vec![Vec::new(); p.blocks.len()];
+    for (&t, blocks) in defs.iter() {
+        let mut work = blocks.clone();
+        let mut placed = HashSet::new();
+        while let Some(b) = work.pop() {
+            for &f in dom.frontier(b) {
+                if live[f].contains(&t) && placed.insert(f) {
+                    let args = vec![Operand::Temp(t); p.blocks[f].preds.len()];
+                    p.blocks[f].phis.push(Phi { dst: t, args });
+                    vars[f].push(t);
+                    work.push(f);
+                }
+            }
+        }
+    }
+
+    // Give every definition a new name, and rewrite each use to the name of
+    // the definition which dominates it
+    let Program { ref mut blocks, ref temps, .. } = *p;
+    let mut names: HashMap<Temp, Vec<Temp>> = HashMap::new();
+    let name = |names: &HashMap<Temp, Vec<Temp>>, o: Operand| match o {
+        Operand::Temp(t) => match names.get(&t).and_then(|s| s.last()) {
+            Some(&t) => Operand::Temp(t),
+            // No definition reaches here, so the value is never used
+            None => Operand::Constant(0),
+        },
+        o => o,
+    };
+    let mut work = vec![Step::Enter(ENTRY)];
+    while let Some(step) = work.pop() {
+        let b = match step {
+            Step::Enter(b) => b,
+            Step::Leave(renamed) => {
+                for t in renamed {
+                    names.get_mut(&t).unwrap().pop();
+                }
+                continue
+            }
+        };
+        let mut renamed = Vec::new();
+        {
+            let block = &mut blocks[b];
+            for (phi, &t) in block.phis.iter_mut().zip(vars[b].iter()) {
+                phi.dst = temps.gen();
+                names.entry(t).or_default().push(phi.dst);
+                renamed.push(t);
+            }
+            for i in block.body.iter_mut() {
+                i.map_uses(|o| name(&names, o));
+                let (t, new) = (i.def(), temps.gen());
+                i.set_def(new);
+                names.entry(t).or_default().push(new);
+                renamed.push(t);
+            }
+            block.exit.map_uses(|o| name(&names, o));
+        }
+        for s in blocks[b].exit.succs() {
+            let j = blocks[s].preds.iter().position(|&pred| pred == b)
+                .unwrap();
+            for (phi, &t) in blocks[s].phis.iter_mut().zip(vars[s].iter()) {
+                phi.args[j] = name(&names, Operand::Temp(t));
+            }
+        }
+        work.push(Step::Leave(renamed));
+        for &c in dom.children(b).iter().rev() {
+            work.push(Step::Enter(c));
+        }
+    }
+}
+
+/// Converts `p` out of SSA form, replacing its phis with copies.
+pub fn destruct(p: &mut Program) {
+    for b in 0..p.blocks.len() {
+        let phis = mem::take(&mut p.blocks[b].phis);
+        if phis.is_empty() {
+            continue
+        }
+        let preds = p.blocks[b].preds.clone();
+        for (j, &pred) in preds.iter().enumerate() {
+            let copies = phis.iter().map(|phi| (phi.dst, phi.args[j]))
+                .collect();
+            let moves = sequentialize(copies, &p.temps);
+            let target = if p.blocks[pred].exit.succs().len() > 1 {
+                let id = p.blocks.len();
+                p.blocks.push(Block {
+                    label: p.labels.gen(),
+                    phis: Vec::new(),
+                    body: Vec::new(),
+                    exit: Exit::Jump(b),
+                    preds: Vec::new(),
+                });
+                let exit = p.blocks[pred].exit.clone();
+                p.blocks[pred].exit =
+                    exit.map_blocks(|s| if s == b { id } else { s });
+                id
+            } else {
+                pred
+            };
+            p.blocks[target].body.extend(moves);
+        }
+    }
+    p.link();
+}
+
+/// Orders the copies `dst <- src`, which all happen at once, into a sequence
+/// of moves with the same effect.
+///
+/// A copy can go ahead once no other copy still needs the old value of its
+/// destination. When only cycles such as swaps are left, one of the values
+/// is saved in a fresh temp to break the cycle.
+fn sequentialize(copies: Vec<(Temp, Operand)>, temps: &TempAllocator)
+                 -> Vec<Instruction> {
+    let mut pending = copies.into_iter()
+        .filter(|&(d, s)| s != Operand::Temp(d))
+        .collect::<Vec<_>>();
+    let mut moves = Vec::new();
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|&(d, _)| {
+            pending.iter().all(|&(_, s)| s != Operand::Temp(d))
+        });
+        match ready {
+            Some(i) => {
+                let (d, s) = pending.remove(i);
+                moves.push(Instruction::Move(d, s));
+            }
+            None => {
+                let (d, _) = pending[0];
+                let saved = temps.gen();
+                moves.push(Instruction::Move(saved, Operand::Temp(d)));
+                for copy in pending.iter_mut() {
+                    if copy.1 == Operand::Temp(d) {
+                        copy.1 = Operand::Temp(saved);
+                    }
+                }
+            }
+        }
+    }
+    moves
+}
+
+/// Returns the temps live on entry to each block of `p`, which must not have
+/// any phis yet.
+fn live_in(p: &Program) -> Vec<HashSet<Temp>> {
+    // The temps each block reads before writing, and the temps it writes
+    let (uses, defs): (Vec<_>, Vec<_>) = p.blocks.iter().map(|block| {
+        let mut uses = HashSet::new();
+        let mut defs = HashSet::new();
+        let mut read = |o: Operand, defs: &HashSet<Temp>| {
+            if let Operand::Temp(t) = o {
+                if !defs.contains(&t) {
+                    uses.insert(t);
+                }
+            }
+        };
+        for i in block.body.iter() {
+            for o in i.uses() {
+                read(o, &defs);
+            }
+            defs.insert(i.def());
+        }
+        for o in block.exit.uses() {
+            read(o, &defs);
+        }
+        (uses, defs)
+    }).unzip();
+
+    // Information flows backwards, so visiting blocks in postorder reaches
+    // the fixed point quickly
+    let order = dom::postorder(p);
+    let mut live = vec![HashSet::new(); p.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &b in order.iter() {
+            let mut inn = uses[b].clone();
+            for s in p.blocks[b].exit.succs() {
+                inn.extend(live[s].iter().filter(|t| !defs[b].contains(t)));
+            }
+            if inn.len() != live[b].len() {
+                live[b] = inn;
+                changed = true;
+            }
+        }
+    }
+    live
+}
@@ -5,20 +5,33 @@
 
 use std::fmt;
 
-use util::{Marked, Errors, Symbol};
+use util::{Mark, Marked, Errors, Symbol};
 
 pub struct Program {
     pub statements: Vec<Statement>,
+    /// The closing brace of `main`
+    pub end: Mark,
     pub errors: Errors,
 }
 
 pub type Statement = Marked<Statement_>;
 #[derive(Clone)]
 pub enum Statement_ {
-    Decl(Ident),
-    DeclAssign(Ident, Expr),
+    Decl(Type, Ident),
+    DeclAssign(Type, Ident, Expr),
     Assign(Ident, Expr),
+    /// `x++` or `x--`, which are statements rather than expressions
+    Postop(Ident, Operator),
     Return(Expr),
+    Block(Vec<Statement>),
+    If(Expr, Box<Statement>, Option<Box<Statement>>),
+    While(Expr, Box<Statement>),
+    /// `for (init; cond; step) body`, where `init` and `step` are optional
+    For(Option<Box<Statement>>, Expr, Option<Box<Statement>>, Box<Statement>),
+    Break,
+    Continue,
+    /// A statement which failed to parse, and has already been reported
+    Error,
 }
 
 pub type Expr = Marked<Expr_>;
@@ -26,12 +39,21 @@ pub type Expr = Marked<Expr_>;
 pub enum Expr_ {
     Variable(Ident),
     Constant(u32),
+    Bool(bool),
     Unary(Operator, Box<Expr>),
     Binary(Operator, Box<Expr>, Box<Expr>),
+    /// `cond ? e1 : e2`
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
 }
 
 pub type Ident = Symbol;
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool,
+}
+
 #[derive(Copy, Clone)]
 pub enum Operator {
     Plus,
@@ -39,31 +61,95 @@ pub enum Operator {
     Times,
     DividedBy,
     Modulo,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
     Negative,
+    Not,
+    BitNot,
+    Increment,
     Decrement,
 }
 
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(writeln!(f, "int main() {{"));
+        writeln!(f, "int main() {{")?;
         for stm in self.statements.iter() {
-            try!(writeln!(f, "  {}", stm));
+            fmt_stm(f, &stm.node, 1)?;
         }
         writeln!(f, "}}")
     }
 }
 
+/// Prints a statement on its own line(s), indented `depth` levels.
+fn fmt_stm(f: &mut fmt::Formatter, stm: &Statement_, depth: usize)
+           -> fmt::Result {
+    let indent = "  ".repeat(depth);
+    match *stm {
+        Statement_::Block(ref stms) => {
+            writeln!(f, "{}{{", indent)?;
+            for s in stms.iter() {
+                fmt_stm(f, &s.node, depth + 1)?;
+            }
+            writeln!(f, "{}}}", indent)
+        }
+        Statement_::If(ref e, ref s1, ref s2) => {
+            writeln!(f, "{}if ({})", indent, e)?;
+            fmt_stm(f, &s1.node, depth + 1)?;
+            match *s2 {
+                Some(ref s2) => {
+                    writeln!(f, "{}else", indent)?;
+                    fmt_stm(f, &s2.node, depth + 1)
+                }
+                None => Ok(()),
+            }
+        }
+        Statement_::While(ref e, ref s) => {
+            writeln!(f, "{}while ({})", indent, e)?;
+            fmt_stm(f, &s.node, depth + 1)
+        }
+        Statement_::For(ref init, ref e, ref step, ref s) => {
+            let simp = |s: &Option<Box<Statement>>| match *s {
+                Some(ref s) => s.to_string().trim_end_matches(';').to_string(),
+                None => String::new(),
+            };
+            writeln!(f, "{}for ({}; {}; {})", indent, simp(init), e,
+                          simp(step))?;
+            fmt_stm(f, &s.node, depth + 1)
+        }
+        ref s => writeln!(f, "{}{}", indent, s),
+    }
+}
+
 impl fmt::Display for Statement_ {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Statement_::Decl(ref id) => write!(f, "int {};", id),
-            Statement_::DeclAssign(ref id, ref expr) => {
-                write!(f, "int {} = {};", id, expr)
+            Statement_::Decl(ref ty, ref id) => write!(f, "{} {};", ty, id),
+            Statement_::DeclAssign(ref ty, ref id, ref expr) => {
+                write!(f, "{} {} = {};", ty, id, expr)
             }
             Statement_::Assign(ref id, ref expr) => {
                 write!(f, "{} = {};", id, expr)
             }
+            Statement_::Postop(ref id, ref op) => write!(f, "{}{};", id, op),
             Statement_::Return(ref expr) => write!(f, "return {};", expr),
+            Statement_::Break => write!(f, "break;"),
+            Statement_::Continue => write!(f, "continue;"),
+            Statement_::Error => write!(f, "<error>;"),
+            Statement_::Block(..) |
+            Statement_::If(..) |
+            Statement_::While(..) |
+            Statement_::For(..) => fmt_stm(f, self, 0),
         }
     }
 }
@@ -73,10 +159,23 @@ impl fmt::Display for Expr_ {
         match *self {
             Expr_::Variable(ref id) => write!(f, "{}", id),
             Expr_::Constant(c) => write!(f, "{}", c),
+            Expr_::Bool(b) => write!(f, "{}", b),
             Expr_::Unary(ref op, ref e) => write!(f, "{}({})", op, e),
             Expr_::Binary(ref op, ref e1, ref e2) => {
                 write!(f, "({} {} {})", e1, op, e2)
             }
+            Expr_::Ternary(ref e1, ref e2, ref e3) => {
+                write!(f, "({} ? {} : {})", e1, e2, e3)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Int => "int".fmt(f),
+            Type::Bool => "bool".fmt(f),
         }
     }
 }
@@ -90,6 +189,22 @@ impl fmt::Display for Operator {
             Operator::Times => "*".fmt(f),
             Operator::DividedBy => "/".fmt(f),
             Operator::Modulo => "%".fmt(f),
+            Operator::Less => "<".fmt(f),
+            Operator::LessEq => "<=".fmt(f),
+            Operator::Greater => ">".fmt(f),
+            Operator::GreaterEq => ">=".fmt(f),
+            Operator::Equal => "==".fmt(f),
+            Operator::NotEqual => "!=".fmt(f),
+            Operator::And => "&&".fmt(f),
+            Operator::Or => "||".fmt(f),
+            Operator::BitAnd => "&".fmt(f),
+            Operator::BitOr => "|".fmt(f),
+            Operator::BitXor => "^".fmt(f),
+            Operator::ShiftLeft => "<<".fmt(f),
+            Operator::ShiftRight => ">>".fmt(f),
+            Operator::Not => "!".fmt(f),
+            Operator::BitNot => "~".fmt(f),
+            Operator::Increment => "++".fmt(f),
             Operator::Decrement => "--".fmt(f),
         }
     }
@@ -1,123 +1,223 @@
-use std::borrow::Borrow;
+//! Lexer
+//!
+//! Splits the source of a program into marked tokens, skipping whitespace and
+//! comments. Block comments nest.
 
-use util::{Marked, Mark};
-use super::{intern, parser_panic};
+use util::{Code, Marked, Mark};
+use super::{intern, lexer_error};
 use super::token::Token;
-use std::io;
-
-fn mark_for<R: io::Read>(lexer: &mut Lexer<R>) -> Mark {
-    let ::rustlex::rt::RustLexPos { off, .. } = lexer._input.tok;
-    Mark::new(off, off + lexer.yystr().len())
-}
-
-fn mark<R: io::Read>(tok: Token, lexer: &mut Lexer<R>) -> Option<Marked<Token>> {
-    Some(Marked::new(tok, mark_for(lexer)))
-}
-
-macro_rules! some { ($x:expr) => { |lexer: &mut Lexer<R>| -> Option<Marked<Token>> { mark($x, lexer) } } }
-macro_rules! none { () => { |_: &mut Lexer<R>| -> Option<Marked<Token>> { None } } }
 
 pub type MarkedToken = Marked<Token>;
 
-rustlex! Lexer {
-    token MarkedToken;
-    property comment_depth:usize = 0;
-
-    let WHITESPACE = [' ' '\n' '\t' '\r' '\x09' '\x0A' '\x0B' '\x0C' '\x0D'];
-    let ID = ['A'-'Z''a'-'z''_']['A'-'Z''a'-'z''0'-'9''_']*;
-    let DECNUM = '0' | ['1'-'9']['0'-'9']*;
-    let HEXNUM = '0'["xX"]['0'-'9''a'-'f''A'-'F']+;
-
-    INITIAL {
-        WHITESPACE => none!(),
-
-        ID => |lexer: &mut Lexer<R>| {
-            mark(Token::Ident(intern(lexer.yystr().borrow())), lexer)
-        },
-
-        '(' => some!(Token::Lparen),
-        ')' => some!(Token::Rparen),
-        '{' => some!(Token::Lbrace),
-        '}' => some!(Token::Rbrace),
-
-        ';' => some!(Token::Semi),
-        '=' => some!(Token::Assign),
-        "+=" => some!(Token::Pluseq),
-        "-=" => some!(Token::Minuseq),
-        "*=" => some!(Token::Stareq),
-        "/=" => some!(Token::Slasheq),
-        "%=" => some!(Token::Percenteq),
+pub struct Lexer<'input> {
+    input: &'input str,
+    /// Byte offset of the next character to look at
+    pos: usize,
+    /// Number of block comments open at the end of the input
+    pub comment_depth: usize,
+    /// Where the outermost open block comment started
+    pub comment_start: Mark,
+}
 
-        '+' => some!(Token::Plus),
-        '-' => some!(Token::Minus),
-        '*' => some!(Token::Star),
-        '/' => some!(Token::Slash),
-        '%' => some!(Token::Percent),
+/// Operators, with each listed before any shorter operator it starts with
+const OPERATORS: [(&str, Token); 40] = [
+    ("<<=", Token::Lshifteq),
+    (">>=", Token::Rshifteq),
+    ("+=", Token::Pluseq),
+    ("-=", Token::Minuseq),
+    ("*=", Token::Stareq),
+    ("/=", Token::Slasheq),
+    ("%=", Token::Percenteq),
+    ("&=", Token::Ampeq),
+    ("|=", Token::Pipeeq),
+    ("^=", Token::Careteq),
+    ("<<", Token::Lshift),
+    (">>", Token::Rshift),
+    ("++", Token::Increment),
+    ("--", Token::Decrement),
+    ("<=", Token::Leq),
+    (">=", Token::Geq),
+    ("==", Token::Eqeq),
+    ("!=", Token::Noteq),
+    ("&&", Token::Andand),
+    ("||", Token::Oror),
+    ("(", Token::Lparen),
+    (")", Token::Rparen),
+    ("{", Token::Lbrace),
+    ("}", Token::Rbrace),
+    (";", Token::Semi),
+    ("=", Token::Assign),
+    ("+", Token::Plus),
+    ("-", Token::Minus),
+    ("*", Token::Star),
+    ("/", Token::Slash),
+    ("%", Token::Percent),
+    ("<", Token::Lt),
+    (">", Token::Gt),
+    ("!", Token::Bang),
+    ("~", Token::Tilde),
+    ("&", Token::Amp),
+    ("|", Token::Pipe),
+    ("^", Token::Caret),
+    ("?", Token::Question),
+    (":", Token::Colon),
+];
+
+impl<'input> Lexer<'input> {
+    pub fn new(input: &'input str) -> Lexer<'input> {
+        Lexer {
+            input,
+            pos: 0,
+            comment_depth: 0,
+            comment_start: Mark::new(0, 0),
+        }
+    }
 
-        "--" => some!(Token::Decrement),
+    fn peek(&self, n: usize) -> Option<u8> {
+        self.input.as_bytes().get(self.pos + n).cloned()
+    }
 
-        "struct" => some!(Token::Struct),
-        "typedef" => some!(Token::Typedef),
-        "if" => some!(Token::If),
-        "else" => some!(Token::Else),
-        "while" => some!(Token::While),
-        "for" => some!(Token::For),
-        "continue" => some!(Token::Continue),
-        "break" => some!(Token::Break),
-        "assert" => some!(Token::Assert),
-        "true" => some!(Token::True),
-        "false" => some!(Token::False),
-        "NULL" => some!(Token::Null),
-        "alloc" => some!(Token::Alloc),
-        "alloc_array" => some!(Token::Allocarray),
-        "bool" => some!(Token::Bool),
-        "void" => some!(Token::Void),
-        "char" => some!(Token::Char),
-        "string" => some!(Token::String),
-        "return" => some!(Token::Return),
-        "int" => some!(Token::Int),
-        "main" => some!(Token::Main),
+    fn rest(&self) -> &'input [u8] {
+        &self.input.as_bytes()[self.pos..]
+    }
 
-        DECNUM => |lexer: &mut Lexer<R>| {
-            let n = lexer.yystr()[..].parse().unwrap();
+    /// Advances past the bytes at the current position which satisfy `f`.
+    fn eat_while<F: Fn(u8) -> bool>(&mut self, f: F) {
+        while self.peek(0).is_some_and(&f) {
+            self.pos += 1;
+        }
+    }
 
-            if n > 2u32.pow(31) {
-                parser_panic(format!("Constant {} is too large", n),
-                             mark_for(lexer));
+    /// Skips over whitespace and comments.
+    fn skip(&mut self) {
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some(b' '), _) | (Some(b'\n'), _) | (Some(b'\t'), _) |
+                (Some(b'\r'), _) | (Some(b'\x0b'), _) | (Some(b'\x0c'), _) => {
+                    self.pos += 1;
+                }
+                (Some(b'/'), Some(b'/')) => self.eat_while(|b| b != b'\n'),
+                (Some(b'/'), Some(b'*')) => self.block_comment(),
+                _ => return,
             }
+        }
+    }
 
-            mark(Token::Intconst(n), lexer)
-        },
-
-        HEXNUM => |lexer: &mut Lexer<R>| {
-            let s = lexer.yystr();
-            let i:u32 = u32::from_str_radix(&s[2..], 16).unwrap();
-            mark(Token::Intconst(i), lexer)
-        },
-
-        "/*" => |lexer: &mut Lexer<R>| -> Option<Marked<Token>> {
-            lexer.comment_depth += 1;
-            lexer.COMMENT();
-            None
-        },
+    /// Skips over a block comment, along with any comments nested inside it.
+    fn block_comment(&mut self) {
+        self.comment_start = Mark::new(self.pos, self.pos + 2);
+        self.comment_depth = 1;
+        self.pos += 2;
+        while self.comment_depth > 0 {
+            if self.rest().starts_with(b"/*") {
+                self.comment_depth += 1;
+                self.pos += 2;
+            } else if self.rest().starts_with(b"*/") {
+                self.comment_depth -= 1;
+                self.pos += 2;
+            } else if self.pos < self.input.len() {
+                self.pos += 1;
+            } else {
+                return
+            }
+        }
+    }
 
-        "//" [^'\n']* => none!(),
+    /// Lexes a keyword or identifier.
+    fn word(&mut self) -> Token {
+        let lo = self.pos;
+        self.eat_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+        match &self.input[lo..self.pos] {
+            "struct" => Token::Struct,
+            "typedef" => Token::Typedef,
+            "if" => Token::If,
+            "else" => Token::Else,
+            "while" => Token::While,
+            "for" => Token::For,
+            "continue" => Token::Continue,
+            "break" => Token::Break,
+            "assert" => Token::Assert,
+            "true" => Token::True,
+            "false" => Token::False,
+            "NULL" => Token::Null,
+            "alloc" => Token::Alloc,
+            "alloc_array" => Token::Allocarray,
+            "bool" => Token::Bool,
+            "void" => Token::Void,
+            "char" => Token::Char,
+            "string" => Token::String,
+            "return" => Token::Return,
+            "int" => Token::Int,
+            "main" => Token::Main,
+            s => Token::Ident(intern(s)),
+        }
     }
 
-    COMMENT {
-        . => none!(),
+    /// Lexes a decimal or hexadecimal constant. Constants which are too large
+    /// are reported, and lexed as 0 so that parsing can carry on.
+    fn number(&mut self) -> Token {
+        let lo = self.pos;
+        let hex = self.peek(0) == Some(b'0') &&
+            (self.peek(1) == Some(b'x') || self.peek(1) == Some(b'X')) &&
+            self.peek(2).is_some_and(|b| b.is_ascii_hexdigit());
+        let n = if hex {
+            self.pos += 2;
+            self.eat_while(|b| b.is_ascii_hexdigit());
+            u32::from_str_radix(&self.input[lo + 2..self.pos], 16).ok()
+        } else if self.peek(0) == Some(b'0') {
+            // Decimal constants have no leading zeros, so `0` stands alone
+            self.pos += 1;
+            Some(0)
+        } else {
+            self.eat_while(|b| b.is_ascii_digit());
+            self.input[lo..self.pos].parse::<u32>().ok()
+                .and_then(|n| if n <= 2u32.pow(31) { Some(n) } else { None })
+        };
+        Token::Intconst(n.unwrap_or_else(|| {
+            lexer_error(Code::ConstantTooLarge,
+                        format!("Constant {} is too large",
+                                &self.input[lo..self.pos]),
+                        Mark::new(lo, self.pos));
+            0
+        }))
+    }
 
-        "*/" => |lexer: &mut Lexer<R>| -> Option<Marked<Token>> {
-            lexer.comment_depth -= 1;
-            if lexer.comment_depth == 0 {
-                lexer.INITIAL();
-            }
-            None
-        },
+    fn operator(&mut self) -> Option<Token> {
+        let rest = self.rest();
+        let found = OPERATORS.iter()
+            .find(|&&(op, _)| rest.starts_with(op.as_bytes()));
+        found.map(|&(op, ref tok)| {
+            self.pos += op.len();
+            tok.clone()
+        })
+    }
+}
 
-        "/*" => |lexer: &mut Lexer<R>| -> Option<Marked<Token>> {
-            lexer.comment_depth += 1;
-            None
-        },
+impl<'input> Iterator for Lexer<'input> {
+    type Item = MarkedToken;
+
+    fn next(&mut self) -> Option<MarkedToken> {
+        loop {
+            self.skip();
+            let lo = self.pos;
+            let c = self.peek(0)?;
+            let tok = if c.is_ascii_alphabetic() || c == b'_' {
+                self.word()
+            } else if c.is_ascii_digit() {
+                self.number()
+            } else if let Some(tok) = self.operator() {
+                tok
+            } else {
+                // Characters which cannot start a token are reported and
+                // skipped
+                let c = self.input[lo..].chars().next().unwrap();
+                self.pos += c.len_utf8();
+                lexer_error(Code::InvalidCharacter,
+                            format!("invalid character `{}`", c),
+                            Mark::new(lo, self.pos));
+                continue
+            };
+            return Some(Marked::new(tok, Mark::new(lo, self.pos)))
+        }
     }
 }
@@ -11,12 +11,15 @@ use std::thread;
 use std::sync::mpsc::channel;
 use self::lalrpop_util::ParseError;
 
-use util::{Errors, CodeMap, SymbolGenerator, Mark, DUMMY_MARK, Symbol};
+use util::{Code, Diagnostic, Errors, ErrorFormat, CodeMap, Severity,
+           SymbolGenerator, Mark, Marked, Symbol};
 use self::lexer::Lexer;
+use self::token::Token;
 
 pub mod ast;
 mod token;
 mod lexer;
+#[allow(clippy::all, unused_parens)]
 mod parser;
 
 thread_local! {
@@ -25,17 +28,108 @@ thread_local! {
 }
 
 thread_local! {
-    static ERRORS: RefCell<Option<Errors>> = RefCell::new(None)
+    static ERRORS: RefCell<Option<Errors>> = const { RefCell::new(None) }
 }
 
-pub fn parser_panic(s: String, m: Mark) -> ! {
+pub fn parser_panic(code: Code, s: String, m: Mark) -> ! {
     ERRORS.with(|errors| {
         errors.borrow().as_ref().expect("Parser errors struct not created")
-            .die(&m, &s);
+            .die(code, &m, &s);
     });
     unreachable!()
 }
 
+/// Reports an error in the program being parsed, and carries on parsing.
+fn report(d: Diagnostic) {
+    ERRORS.with(|errors| {
+        errors.borrow().as_ref().expect("Parser errors struct not created")
+            .emit(d);
+    });
+}
+
+/// Reports an error found by the lexer, which then carries on with the rest
+/// of the input.
+pub fn lexer_error(code: Code, s: String, m: Mark) {
+    report(Diagnostic::new(Severity::Error, code, &m, &s));
+}
+
+/// Reports `++` or `--` used in an expression, before its operand if `prefix`
+/// holds and after it otherwise.
+pub fn postop_in_expression(op: &Marked<Token>, prefix: bool) {
+    let msg = format!("`{}` cannot be used in an expression", op.node);
+    let d = Diagnostic::new(Severity::Error, Code::IncrementInExpression,
+                            &op.mark, &msg);
+    report(match op.node {
+        // `--x` is not `-(-x)`, since the lexer always reads `--` as a whole
+        Token::Decrement if prefix => d.hint("write `- -` to negate twice"),
+        _ => d.hint(&format!("use `x{};` as a statement of its own",
+                             op.node)),
+    });
+}
+
+/// Reports a syntax error in `src`, whose tokens are at `marks`, with hints
+/// for common mistakes.
+fn syntax_error(err: ParseError<usize, Token, &'static str>, marks: &[Mark],
+                src: &str) {
+    let d = match err {
+        ParseError::UnrecognizedToken {token, expected} => {
+            let (code, mark, found) = match token {
+                // Tokens are quoted as they are spelled in the source, so
+                // that `0xFF` does not turn into `255`
+                Some((lo, _, hi)) => {
+                    (Code::UnexpectedToken, Mark::new(lo, hi),
+                     format!("`{}`", &src[lo..hi]))
+                }
+                None => {
+                    let end = marks.last().map_or(0, |m| m.hi);
+                    (Code::UnexpectedEof, Mark::new(end, end),
+                     String::from("end of file"))
+                }
+            };
+            let msg = format!("expected {}, found {}",
+                              Token::describe(&expected), found);
+            let mut d = Diagnostic::new(Severity::Error, code, &mark, &msg);
+
+            let expects = |name: &str| {
+                expected.iter().any(|e| e.trim_matches('"') == name)
+            };
+            // A statement which ends at a line break is probably missing its
+            // semicolon
+            let prev = marks.iter().take_while(|m| m.hi <= mark.lo).last();
+            if let Some(prev) = prev {
+                if expects("Semi") && src[prev.hi..mark.lo].contains('\n') {
+                    d = d.label(&Mark::new(prev.hi, prev.hi), "add `;` here");
+                }
+            }
+            match token {
+                Some((_, Token::Assign, _)) if expects("Eqeq") => {
+                    d = d.hint("use `==` to compare values");
+                }
+                Some((_, Token::Eqeq, _)) if expects("Assign") => {
+                    d = d.hint("use `=` to assign to a variable");
+                }
+                _ => {}
+            }
+            d
+        }
+        ParseError::ExtraToken {token: (lo, _, hi)} => {
+            let msg = format!("found `{}` after the end of `main`",
+                              &src[lo..hi]);
+            Diagnostic::new(Severity::Error, Code::ExtraToken,
+                            &Mark::new(lo, hi), &msg)
+        }
+        ParseError::InvalidToken {location} => {
+            Diagnostic::new(Severity::Error, Code::UnexpectedToken,
+                            &Mark::new(location, location), "invalid token")
+        }
+        ParseError::User {error} => {
+            Diagnostic::unmarked(Severity::Error, Code::UnexpectedToken,
+                                 error)
+        }
+    };
+    report(d);
+}
+
 pub fn intern(s: &str) -> Symbol {
     let mut symbol = None;
     GENERATOR.with(
@@ -47,9 +141,10 @@ pub fn intern(s: &str) -> Symbol {
 // If 64MB doesn't suffice, we'll remove the test
 const STACK_SIZE: usize = 64 * 1024 * 1024;
 
-pub fn parse(input: &Path) -> io::Result<ast::Program> {
+/// Parses the file `input`, reporting any errors in it in the given format.
+pub fn parse(input: &Path, format: ErrorFormat) -> io::Result<ast::Program> {
     let mut contents = String::new();
-    try!(File::open(input).and_then(|mut f| f.read_to_string(&mut contents)));
+    File::open(input).and_then(|mut f| f.read_to_string(&mut contents))?;
 
     // Rust's default stack size is relatively small, so certain tests cases
     // which are highly recursive, e.g. int x = 1 - 1 - 1 - 1 - ... will
@@ -62,51 +157,43 @@ pub fn parse(input: &Path) -> io::Result<ast::Program> {
         ERRORS.with(|errors| {
             let input = input.to_owned();
             *errors.borrow_mut() =
-                Some(Errors::new(CodeMap::new(contents.clone(), input)));
+                Some(Errors::new(CodeMap::new(contents.clone(), input),
+                                 format));
         });
 
-        let mut lexer = Lexer::new(io::BufReader::new(contents.as_bytes()));
-        let mut tokens = vec![];
-        while let Some(tok) = lexer.next() {
-            tokens.push((tok.mark.lo, tok.node, tok.mark.hi));
-        }
+        let mut lexer = Lexer::new(&contents);
+        let tokens = lexer.by_ref()
+            .map(|tok| (tok.mark.lo, tok.node, tok.mark.hi))
+            .collect::<Vec<_>>();
 
         if lexer.comment_depth > 0 {
-            parser_panic(String::from("Unclosed block comment"), DUMMY_MARK);
+            parser_panic(Code::UnclosedComment,
+                         String::from("Unclosed block comment"),
+                         lexer.comment_start);
         }
 
         // We need to store here so error printing below can work.
         GENERATOR.with(|generator| (*generator.borrow()).clone().store());
 
-        let stmts = parser::parse_Program(tokens).unwrap_or_else(|err| { match err {
-            ParseError::UnrecognizedToken {token, expected} => match token {
-                Some((lo, tok, hi)) => {
-                    let err =
-                        format!("Parse error: expected tokens {:?}, found token {:?}",
-                                expected, tok);
-                    parser_panic(err, Mark::new(lo, hi));
-                },
-                None => {
-                    let err =
-                        format!("Parse error: expected tokens {:?}, found EOF",
-                                expected);
-                    parser_panic(String::from(err), DUMMY_MARK);
-                }
-            },
-            ParseError::ExtraToken {token} => {
-                let (lo, tok, hi) = token;
-                let err = format!("Parse error: found extra token {:?}", tok);
-                parser_panic(err, Mark::new(lo, hi));
-            },
-            ParseError::User {error} => {
-                let err = format!("Parse error: {:?}", error);
-                parser_panic(err, DUMMY_MARK);
-            },
-        } } );
+        // The parser skips over statements with syntax errors so it can report
+        // as many as possible, but some errors leave no program to carry on
+        // with.
+        let marks = tokens.iter().map(|&(lo, _, hi)| Mark::new(lo, hi))
+            .collect::<Vec<_>>();
+        let mut recovered = Vec::new();
+        let result = parser::ProgramParser::new().parse(&mut recovered, tokens);
+        for e in recovered {
+            syntax_error(e.error, &marks, &contents);
+        }
+        let (stmts, end) = result.unwrap_or_else(|err| {
+            syntax_error(err, &marks, &contents);
+            ERRORS.with(|errors| errors.borrow().as_ref().unwrap().check());
+            unreachable!()
+        });
 
         ERRORS.with(|errors| {
             GENERATOR.with(|generator| {
-                t2.send((stmts,
+                t2.send((stmts, end,
                          errors.borrow().clone().unwrap(),
                          generator.borrow().clone())).unwrap();
             });
@@ -114,11 +201,12 @@ pub fn parse(input: &Path) -> io::Result<ast::Program> {
     }).unwrap();
 
     t1.send((contents, input.to_path_buf())).unwrap();
-    let (stmts, errors, generator) = r2.recv().unwrap();
+    let (stmts, end, errors, generator) = r2.recv().unwrap();
     generator.store();
 
     Ok(ast::Program {
         statements: stmts,
-        errors: errors,
+        end,
+        errors,
     })
 }
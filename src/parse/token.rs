@@ -1,6 +1,8 @@
+use std::fmt;
+
 use super::ast;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Token {
     Struct,
     Typedef,
@@ -37,9 +39,241 @@ pub enum Token {
     Stareq,
     Slasheq,
     Percenteq,
+    Ampeq,
+    Pipeeq,
+    Careteq,
+    Lshifteq,
+    Rshifteq,
+    Lt,
+    Leq,
+    Gt,
+    Geq,
+    Eqeq,
+    Noteq,
+    Andand,
+    Oror,
+    Bang,
+    Tilde,
+    Amp,
+    Pipe,
+    Caret,
+    Lshift,
+    Rshift,
+    Question,
+    Colon,
     Lbrace,
     Rbrace,
     Lparen,
     Rparen,
+    Increment,
     Decrement,
 }
+
+/// Terminals which can start a statement
+const STATEMENT: [&str; 12] = [
+    "Bool", "Break", "Continue", "For", "Ident", "If", "Int", "Lbrace",
+    "Lparen", "Main", "Return", "While",
+];
+
+/// Terminals which can start an expression
+const EXPRESSION: [&str; 11] = [
+    "Bang", "Decrement", "False", "Ident", "Increment", "Intconst", "Lparen",
+    "Main", "Minus", "Tilde", "True",
+];
+
+/// Terminals which can follow a variable at the start of a statement
+const ASSIGNMENT: [&str; 13] = [
+    "Ampeq", "Assign", "Careteq", "Decrement", "Increment", "Lshifteq",
+    "Minuseq", "Percenteq", "Pipeeq", "Pluseq", "Rshifteq", "Slasheq",
+    "Stareq",
+];
+
+/// Terminals which can follow an expression, other than those closing it.
+/// `++` and `--` only parse here to be reported as errors.
+const OPERATOR: [&str; 21] = [
+    "Amp", "Andand", "Caret", "Decrement", "Eqeq", "Geq", "Gt", "Increment",
+    "Leq", "Lshift", "Lt", "Minus", "Noteq", "Oror", "Percent", "Pipe",
+    "Plus", "Question", "Rshift", "Slash", "Star",
+];
+
+impl Token {
+    /// Returns the token named `name` in the grammar, or `None` for tokens
+    /// which carry a value.
+    fn from_name(name: &str) -> Option<Token> {
+        Some(match name {
+            "Struct" => Token::Struct,
+            "Typedef" => Token::Typedef,
+            "If" => Token::If,
+            "Else" => Token::Else,
+            "While" => Token::While,
+            "For" => Token::For,
+            "Continue" => Token::Continue,
+            "Break" => Token::Break,
+            "Assert" => Token::Assert,
+            "True" => Token::True,
+            "False" => Token::False,
+            "Null" => Token::Null,
+            "Alloc" => Token::Alloc,
+            "Allocarray" => Token::Allocarray,
+            "Bool" => Token::Bool,
+            "Void" => Token::Void,
+            "Char" => Token::Char,
+            "String" => Token::String,
+            "Int" => Token::Int,
+            "Semi" => Token::Semi,
+            "Return" => Token::Return,
+            "Main" => Token::Main,
+            "Plus" => Token::Plus,
+            "Minus" => Token::Minus,
+            "Star" => Token::Star,
+            "Slash" => Token::Slash,
+            "Percent" => Token::Percent,
+            "Assign" => Token::Assign,
+            "Pluseq" => Token::Pluseq,
+            "Minuseq" => Token::Minuseq,
+            "Stareq" => Token::Stareq,
+            "Slasheq" => Token::Slasheq,
+            "Percenteq" => Token::Percenteq,
+            "Ampeq" => Token::Ampeq,
+            "Pipeeq" => Token::Pipeeq,
+            "Careteq" => Token::Careteq,
+            "Lshifteq" => Token::Lshifteq,
+            "Rshifteq" => Token::Rshifteq,
+            "Lt" => Token::Lt,
+            "Leq" => Token::Leq,
+            "Gt" => Token::Gt,
+            "Geq" => Token::Geq,
+            "Eqeq" => Token::Eqeq,
+            "Noteq" => Token::Noteq,
+            "Andand" => Token::Andand,
+            "Oror" => Token::Oror,
+            "Bang" => Token::Bang,
+            "Tilde" => Token::Tilde,
+            "Amp" => Token::Amp,
+            "Pipe" => Token::Pipe,
+            "Caret" => Token::Caret,
+            "Lshift" => Token::Lshift,
+            "Rshift" => Token::Rshift,
+            "Question" => Token::Question,
+            "Colon" => Token::Colon,
+            "Lbrace" => Token::Lbrace,
+            "Rbrace" => Token::Rbrace,
+            "Lparen" => Token::Lparen,
+            "Rparen" => Token::Rparen,
+            "Increment" => Token::Increment,
+            "Decrement" => Token::Decrement,
+            _ => return None,
+        })
+    }
+
+    /// Describes the tokens named in `expected`, as reported by the parser,
+    /// such as "`;` or an operator".
+    ///
+    /// Tokens which together make up the start of a statement or expression,
+    /// or all of the assignment or binary operators, are described as a
+    /// group. Nothing is expected after the end of the program.
+    pub fn describe(expected: &[String]) -> String {
+        let mut names = expected.iter()
+            .map(|s| s.trim_matches('"'))
+            .collect::<Vec<_>>();
+        let mut items = Vec::new();
+        let groups = [
+            ("a statement", &STATEMENT[..]),
+            ("an expression", &EXPRESSION[..]),
+            ("an assignment operator", &ASSIGNMENT[..]),
+            ("an operator", &OPERATOR[..]),
+        ];
+        for &(group, tokens) in groups.iter() {
+            if tokens.iter().all(|t| names.contains(t)) {
+                names.retain(|n| !tokens.contains(n));
+                items.push(group.to_string());
+            }
+        }
+        for name in names {
+            items.push(match Token::from_name(name) {
+                Some(tok) => format!("`{}`", tok),
+                None if name == "Ident" => String::from("an identifier"),
+                None if name == "Intconst" => String::from("a number"),
+                None => name.to_string(),
+            });
+        }
+
+        match items.split_last() {
+            None => String::from("end of file"),
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        }
+    }
+}
+
+/// Prints the token as it is spelled in the source.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Token::Intconst(n) => return write!(f, "{}", n),
+            Token::Ident(id) => return write!(f, "{}", id),
+            Token::Struct => "struct",
+            Token::Typedef => "typedef",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::While => "while",
+            Token::For => "for",
+            Token::Continue => "continue",
+            Token::Break => "break",
+            Token::Assert => "assert",
+            Token::True => "true",
+            Token::False => "false",
+            Token::Null => "NULL",
+            Token::Alloc => "alloc",
+            Token::Allocarray => "alloc_array",
+            Token::Bool => "bool",
+            Token::Void => "void",
+            Token::Char => "char",
+            Token::String => "string",
+            Token::Int => "int",
+            Token::Semi => ";",
+            Token::Return => "return",
+            Token::Main => "main",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Assign => "=",
+            Token::Pluseq => "+=",
+            Token::Minuseq => "-=",
+            Token::Stareq => "*=",
+            Token::Slasheq => "/=",
+            Token::Percenteq => "%=",
+            Token::Ampeq => "&=",
+            Token::Pipeeq => "|=",
+            Token::Careteq => "^=",
+            Token::Lshifteq => "<<=",
+            Token::Rshifteq => ">>=",
+            Token::Lt => "<",
+            Token::Leq => "<=",
+            Token::Gt => ">",
+            Token::Geq => ">=",
+            Token::Eqeq => "==",
+            Token::Noteq => "!=",
+            Token::Andand => "&&",
+            Token::Oror => "||",
+            Token::Bang => "!",
+            Token::Tilde => "~",
+            Token::Amp => "&",
+            Token::Pipe => "|",
+            Token::Caret => "^",
+            Token::Lshift => "<<",
+            Token::Rshift => ">>",
+            Token::Question => "?",
+            Token::Colon => ":",
+            Token::Lbrace => "{",
+            Token::Rbrace => "}",
+            Token::Lparen => "(",
+            Token::Rparen => ")",
+            Token::Increment => "++",
+            Token::Decrement => "--",
+        };
+        f.write_str(s)
+    }
+}
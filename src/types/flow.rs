@@ -0,0 +1,213 @@
+//! Control flow checks
+//!
+//! Verifies that every variable is definitely assigned along every path to
+//! each of its uses, and that every path through `main` ends in a `return`.
+
+use std::collections::HashSet;
+
+use parse::ast::{self, Expr_, Statement_};
+use util::{Code, Errors, Mark};
+
+/// The variables definitely assigned at some point in the program.
+///
+/// Control never reaches the code after a `return`, `break` or `continue`, so
+/// every variable is vacuously assigned there.
+#[derive(Clone)]
+enum Assigned {
+    All,
+    Vars(HashSet<ast::Ident>),
+}
+
+struct Checker<'a> {
+    errors: &'a Errors,
+    /// Variables currently in scope
+    declared: HashSet<ast::Ident>,
+    /// For each enclosing loop, innermost last, the variables assigned at
+    /// every `continue` seen so far
+    continues: Vec<Assigned>,
+}
+
+pub fn check(p: &ast::Program) {
+    let mut checker = Checker {
+        errors: &p.errors,
+        declared: HashSet::new(),
+        continues: Vec::new(),
+    };
+    checker.block(&p.statements, Assigned::Vars(HashSet::new()));
+    if !p.statements.iter().any(returns) {
+        p.errors.add(Code::MissingReturn, &p.end,
+                     "main does not return along every path");
+    }
+}
+
+/// Returns whether every path through `s` ends in a `return`.
+fn returns(s: &ast::Statement) -> bool {
+    match s.node {
+        Statement_::Return(..) => true,
+        // Whatever failed to parse might have returned
+        Statement_::Error => true,
+        Statement_::Block(ref stms) => stms.iter().any(returns),
+        Statement_::If(_, ref s1, Some(ref s2)) => returns(s1) && returns(s2),
+        _ => false,
+    }
+}
+
+impl Assigned {
+    fn contains(&self, id: &ast::Ident) -> bool {
+        match *self {
+            Assigned::All => true,
+            Assigned::Vars(ref vars) => vars.contains(id),
+        }
+    }
+
+    fn insert(&mut self, id: ast::Ident) {
+        if let Assigned::Vars(ref mut vars) = *self {
+            vars.insert(id);
+        }
+    }
+
+    /// Keeps only the variables in `vars`.
+    fn restrict(self, vars: &HashSet<ast::Ident>) -> Assigned {
+        match self {
+            Assigned::All => Assigned::All,
+            Assigned::Vars(v) => {
+                Assigned::Vars(v.into_iter().filter(|id| vars.contains(id))
+                                .collect())
+            }
+        }
+    }
+
+    /// Returns the variables assigned along both paths where control merges.
+    fn meet(self, other: Assigned) -> Assigned {
+        match (self, other) {
+            (Assigned::All, a) | (a, Assigned::All) => a,
+            (Assigned::Vars(a), Assigned::Vars(b)) => {
+                Assigned::Vars(a.intersection(&b).cloned().collect())
+            }
+        }
+    }
+}
+
+impl<'a> Checker<'a> {
+    /// Checks statements run in sequence, returning what is assigned after
+    /// the last one.
+    fn block(&mut self, stms: &[ast::Statement], a: Assigned) -> Assigned {
+        self.scope(|c| stms.iter().fold(a, |a, s| c.stm(s, a)))
+    }
+
+    /// Runs `f` in a nested scope, forgetting about the variables declared
+    /// inside it afterwards.
+    fn scope<F>(&mut self, f: F) -> Assigned
+        where F: FnOnce(&mut Self) -> Assigned
+    {
+        let outer = self.declared.clone();
+        let a = f(self);
+        self.declared = outer;
+        a.restrict(&self.declared)
+    }
+
+    fn stm(&mut self, s: &ast::Statement, mut a: Assigned) -> Assigned {
+        match s.node {
+            Statement_::Decl(_, id) => {
+                self.declared.insert(id);
+                a
+            }
+            Statement_::DeclAssign(_, id, ref e) => {
+                self.declared.insert(id);
+                self.expr(e, &a);
+                a.insert(id);
+                a
+            }
+            Statement_::Assign(id, ref e) => {
+                self.expr(e, &a);
+                a.insert(id);
+                a
+            }
+            Statement_::Postop(id, _) => {
+                self.var(id, &s.mark, &a);
+                a
+            }
+            Statement_::Return(ref e) => {
+                self.expr(e, &a);
+                Assigned::All
+            }
+            Statement_::Break | Statement_::Error => Assigned::All,
+            Statement_::Continue => {
+                // Outside of a loop, the typechecker has already reported
+                // the `continue`
+                if let Some(continues) = self.continues.last_mut() {
+                    *continues = continues.clone().meet(a);
+                }
+                Assigned::All
+            }
+            Statement_::Block(ref stms) => self.block(stms, a),
+            Statement_::If(ref e, ref s1, ref s2) => {
+                self.expr(e, &a);
+                let a1 = self.scope(|c| c.stm(s1, a.clone()));
+                let a2 = match *s2 {
+                    Some(ref s2) => self.scope(|c| c.stm(s2, a)),
+                    None => a,
+                };
+                a1.meet(a2)
+            }
+            // The body of a loop may never run, so nothing it assigns counts
+            // afterwards
+            Statement_::While(ref e, ref body) => {
+                self.expr(e, &a);
+                self.body(body, a.clone());
+                a
+            }
+            Statement_::For(ref init, ref e, ref step, ref body) => {
+                self.scope(|c| {
+                    let a = match *init {
+                        Some(ref init) => c.stm(init, a),
+                        None => a,
+                    };
+                    c.expr(e, &a);
+                    // The step runs after the body finishes or continues
+                    let after = c.body(body, a.clone());
+                    if let Some(ref step) = *step {
+                        c.stm(step, after);
+                    }
+                    a
+                })
+            }
+        }
+    }
+
+    /// Checks the body of a loop, returning the variables assigned whenever
+    /// control reaches the end of an iteration.
+    fn body(&mut self, body: &ast::Statement, a: Assigned) -> Assigned {
+        self.continues.push(Assigned::All);
+        let a = self.scope(|c| c.stm(body, a));
+        let continues = self.continues.pop().unwrap();
+        a.meet(continues)
+    }
+
+    /// Reports a use of `id` at `mark` if it is not definitely assigned.
+    fn var(&self, id: ast::Ident, mark: &Mark, a: &Assigned) {
+        // Undeclared variables are reported by the typechecker
+        if self.declared.contains(&id) && !a.contains(&id) {
+            let msg = format!("uninitialized variable `{}`", id);
+            self.errors.add(Code::UninitializedVariable, mark, &msg);
+        }
+    }
+
+    /// Reports every variable used in `e` which is not definitely assigned.
+    fn expr(&self, e: &ast::Expr, a: &Assigned) {
+        match e.node {
+            Expr_::Variable(id) => self.var(id, &e.mark, a),
+            Expr_::Constant(..) | Expr_::Bool(..) => {}
+            Expr_::Unary(_, ref e) => self.expr(e, a),
+            Expr_::Binary(_, ref e1, ref e2) => {
+                self.expr(e1, a);
+                self.expr(e2, a);
+            }
+            Expr_::Ternary(ref c, ref e1, ref e2) => {
+                self.expr(c, a);
+                self.expr(e1, a);
+                self.expr(e2, a);
+            }
+        }
+    }
+}
@@ -1,18 +1,27 @@
-/// Type Checker
-///
-/// This is a simple typechecker that only verifies simple properties of our
-/// program. Right now we only have one type, so there's not really a whole lot
-/// of typechecking to do.
+//! Type Checker
+//!
+//! Checks that every expression is used at the right type, along with a few
+//! simple properties such as variables being declared before they are used.
+//! Properties that depend on control flow are checked by `flow`.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use parse::ast::{self, Expr_, Statement_};
-use util::{Mark, DUMMY_MARK};
+use parse::ast::{self, Expr_, Operator, Statement_, Type};
+use util::{Code, Diagnostic, Mark, Severity};
+
+mod flow;
 
 struct TypeChecker<'a> {
     prog: &'a ast::Program,
-    syms: HashMap<ast::Ident, bool>,
-    return_found: bool,
+    /// The type of each variable in scope, and where it was declared
+    syms: HashMap<ast::Ident, (Type, Mark)>,
+    /// Number of loops enclosing the statement being checked
+    loops: usize,
+    /// Whether a statement which failed to parse has been seen. It could have
+    /// declared any variable, so undeclared variables are no longer reported.
+    skipped: bool,
+    /// The variable whose initializer is being checked, along with its type
+    declaring: Option<(ast::Ident, Type)>,
 }
 
 pub fn typecheck(p: &ast::Program) {
@@ -20,81 +29,221 @@ pub fn typecheck(p: &ast::Program) {
     for stm in p.statements.iter() {
         tc.stm(stm);
     }
-    if !tc.return_found {
-        p.errors.add(&DUMMY_MARK, "main does not return");
-    }
+    flow::check(p);
 }
 
 impl<'a> TypeChecker<'a> {
-    fn new(p: &ast::Program) -> TypeChecker {
+    fn new(p: &ast::Program) -> TypeChecker<'_> {
         TypeChecker {
             prog: p,
             syms: HashMap::new(),
-            return_found: false,
+            loops: 0,
+            skipped: false,
+            declaring: None,
         }
     }
 
     fn stm(&mut self, s: &ast::Statement) {
         match s.node {
-            Statement_::Decl(id) => self.check_decl(id, &s.mark),
-            Statement_::DeclAssign(id, ref e) => {
-                self.check_decl(id, &s.mark);
-                self.assign(id, &s.mark, e);
+            Statement_::Decl(ty, id) => self.check_decl(ty, id, &s.mark),
+            // A variable is only in scope once its initializer has been
+            // checked. Reading it in the initializer is reported by the flow
+            // check, as a use before it is initialized.
+            Statement_::DeclAssign(ty, id, ref e) => {
+                self.declaring = Some((id, ty));
+                self.expect(e, ty);
+                self.declaring = None;
+                self.check_decl(ty, id, &s.mark);
             }
             Statement_::Assign(id, ref e) => self.assign(id, &s.mark, e),
-            Statement_::Return(ref e) => {
-                self.expr(e);
-                self.return_found = true;
-                // Define all variables declared before return
-                for (_, val) in self.syms.iter_mut() {
-                    *val = true;
+            Statement_::Postop(id, op) => self.postop(id, op, &s.mark),
+            Statement_::Return(ref e) => self.expect(e, Type::Int),
+            Statement_::Block(ref stms) => {
+                self.scope(|tc| for s in stms.iter() { tc.stm(s) });
+            }
+            Statement_::If(ref e, ref s1, ref s2) => {
+                self.expect(e, Type::Bool);
+                self.scope(|tc| tc.stm(s1));
+                if let Some(ref s2) = *s2 {
+                    self.scope(|tc| tc.stm(s2));
                 }
             }
+            Statement_::While(ref e, ref body) => {
+                self.expect(e, Type::Bool);
+                self.body(body);
+            }
+            Statement_::For(ref init, ref e, ref step, ref body) => {
+                self.scope(|tc| {
+                    if let Some(ref init) = *init {
+                        tc.stm(init);
+                    }
+                    tc.expect(e, Type::Bool);
+                    tc.body(body);
+                    if let Some(ref step) = *step {
+                        match step.node {
+                            Statement_::Decl(..) | Statement_::DeclAssign(..) => {
+                                tc.prog.errors.add(Code::DeclarationInForStep,
+                                    &step.mark,
+                                    "the step of a for loop cannot be a \
+                                     declaration");
+                            }
+                            _ => tc.stm(step),
+                        }
+                    }
+                });
+            }
+            Statement_::Break => self.check_in_loop("break", &s.mark),
+            Statement_::Continue => self.check_in_loop("continue", &s.mark),
+            Statement_::Error => self.skipped = true,
+        }
+    }
+
+    fn check_in_loop(&mut self, keyword: &str, mark: &Mark) {
+        if self.loops == 0 {
+            let msg = format!("`{}` outside of a loop", keyword);
+            self.prog.errors.add(Code::OutsideLoop, mark, &msg);
         }
     }
 
+    /// Checks the body of a loop.
+    fn body(&mut self, body: &ast::Statement) {
+        self.loops += 1;
+        self.scope(|tc| tc.stm(body));
+        self.loops -= 1;
+    }
+
+    /// Runs `f` in a nested scope, so variables it declares are forgotten
+    /// afterwards.
+    fn scope<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        let outer = self.syms.keys().cloned().collect::<HashSet<_>>();
+        f(self);
+        self.syms.retain(|id, _| outer.contains(id));
+    }
+
     fn assign(&mut self, id: ast::Ident, mark: &Mark, e: &ast::Expr) {
-        self.expr(e);
-        match self.syms.insert(id, true) {
-            Some(..) => {}
+        match self.syms.get(&id).cloned() {
+            Some((ty, _)) => self.expect(e, ty),
+            None if self.skipped => { self.expr(e); }
             None => {
+                self.expr(e);
                 let msg = format!("undeclared variable `{}`", id);
-                self.prog.errors.add(mark, &msg);
+                self.prog.errors.add(Code::UndeclaredVariable, mark, &msg);
             }
         }
     }
 
-    fn expr(&mut self, e: &ast::Expr) {
+    /// Checks that `id` is an `int` variable, as `op` needs.
+    fn postop(&mut self, id: ast::Ident, op: Operator, mark: &Mark) {
+        match self.syms.get(&id).cloned() {
+            Some((Type::Int, _)) => {}
+            Some((ty, _)) => {
+                let msg = format!("cannot apply `{}` to a variable of type \
+                                   `{}`", op, ty);
+                self.prog.errors.add(Code::TypeMismatch, mark, &msg);
+            }
+            None if self.skipped => {}
+            None => {
+                let msg = format!("undeclared variable `{}`", id);
+                self.prog.errors.add(Code::UndeclaredVariable, mark, &msg);
+            }
+        }
+    }
+
+    /// Checks that `e` has type `ty`.
+    fn expect(&mut self, e: &ast::Expr, ty: Type) {
+        match self.expr(e) {
+            Some(actual) if actual != ty => {
+                let msg = format!("expected an expression of type `{}`, \
+                                   found `{}`", ty, actual);
+                self.prog.errors.add(Code::TypeMismatch, &e.mark, &msg);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the type of `e`, or `None` if it is ill-typed and an error has
+    /// already been reported.
+    fn expr(&mut self, e: &ast::Expr) -> Option<Type> {
         match e.node {
             Expr_::Variable(id) => {
-                match self.syms.get(&id).map(|b| *b) {
-                    Some(false) => {
-                        let msg = format!("uninitialized variable `{}`", id);
-                        self.prog.errors.add(&e.mark, &msg);
-                    }
-                    Some(..) => {}
-                    None => {
+                match (self.syms.get(&id).cloned(), self.declaring) {
+                    (Some((ty, _)), _) => Some(ty),
+                    (None, Some((d, ty))) if d == id => Some(ty),
+                    (None, _) if self.skipped => None,
+                    (None, _) => {
                         let msg = format!("undeclared variable `{}`", id);
-                        self.prog.errors.add(&e.mark, &msg);
+                        self.prog.errors.add(Code::UndeclaredVariable, &e.mark,
+                                             &msg);
+                        None
                     }
                 }
             }
-            Expr_::Constant(..) => {}
-            Expr_::Unary(_, ref e) => self.expr(e),
-            Expr_::Binary(_, ref e1, ref e2) => {
-                self.expr(e1);
-                self.expr(e2);
+            Expr_::Constant(..) => Some(Type::Int),
+            Expr_::Bool(..) => Some(Type::Bool),
+            Expr_::Unary(Operator::Not, ref e) => {
+                self.expect(e, Type::Bool);
+                Some(Type::Bool)
+            }
+            Expr_::Unary(_, ref e) => {
+                self.expect(e, Type::Int);
+                Some(Type::Int)
+            }
+            Expr_::Binary(op, ref e1, ref e2) => {
+                match op {
+                    Operator::Equal | Operator::NotEqual => {
+                        match (self.expr(e1), self.expr(e2)) {
+                            (Some(t1), Some(t2)) if t1 != t2 => {
+                                let msg = format!("cannot compare `{}` with \
+                                                   `{}`", t1, t2);
+                                self.prog.errors.add(Code::IncomparableTypes,
+                                                     &e.mark, &msg);
+                            }
+                            _ => {}
+                        }
+                        Some(Type::Bool)
+                    }
+                    Operator::And | Operator::Or => {
+                        self.expect(e1, Type::Bool);
+                        self.expect(e2, Type::Bool);
+                        Some(Type::Bool)
+                    }
+                    Operator::Less | Operator::LessEq |
+                    Operator::Greater | Operator::GreaterEq => {
+                        self.expect(e1, Type::Int);
+                        self.expect(e2, Type::Int);
+                        Some(Type::Bool)
+                    }
+                    _ => {
+                        self.expect(e1, Type::Int);
+                        self.expect(e2, Type::Int);
+                        Some(Type::Int)
+                    }
+                }
+            }
+            Expr_::Ternary(ref c, ref e1, ref e2) => {
+                self.expect(c, Type::Bool);
+                match (self.expr(e1), self.expr(e2)) {
+                    (Some(t1), Some(t2)) if t1 != t2 => {
+                        let msg = format!("the branches of `?:` have \
+                                           different types `{}` and `{}`",
+                                          t1, t2);
+                        self.prog.errors.add(Code::TypeMismatch, &e.mark,
+                                             &msg);
+                        None
+                    }
+                    (t1, t2) => t1.or(t2),
+                }
             }
         }
     }
 
-    fn check_decl(&mut self, id: ast::Ident, mark: &Mark) {
-        match self.syms.insert(id, false) {
-            Some(..) => {
-                let msg = format!("redeclared variable `{}`", id);
-                self.prog.errors.add(mark, &msg);
-            }
-            None => {}
+    fn check_decl(&mut self, ty: Type, id: ast::Ident, mark: &Mark) {
+        if let Some((_, prev)) = self.syms.insert(id, (ty, *mark)) {
+            let msg = format!("redeclared variable `{}`", id);
+            self.prog.errors.emit(
+                Diagnostic::new(Severity::Error, Code::RedeclaredVariable,
+                                mark, &msg)
+                    .label(&prev, "previously declared here"));
         }
     }
 }
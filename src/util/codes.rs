@@ -0,0 +1,229 @@
+//! Error codes
+//!
+//! Every kind of diagnostic has a code which never changes, so tools and tests
+//! can recognize it without matching on the text of the message. Codes are
+//! never reused, even if the error they describe goes away.
+
+use std::fmt;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Code {
+    UnclosedComment,
+    ConstantTooLarge,
+    UnexpectedToken,
+    UnexpectedEof,
+    ExtraToken,
+    /// No longer emitted
+    Unsupported,
+    UndeclaredVariable,
+    UninitializedVariable,
+    RedeclaredVariable,
+    MissingReturn,
+    TypeMismatch,
+    IncomparableTypes,
+    OutsideLoop,
+    DeclarationInForStep,
+    InvalidCharacter,
+    IncrementInExpression,
+    IgnoredOption,
+    OptimizationReport,
+}
+
+static ALL: [Code; 18] = [
+    Code::UnclosedComment,
+    Code::ConstantTooLarge,
+    Code::UnexpectedToken,
+    Code::UnexpectedEof,
+    Code::ExtraToken,
+    Code::Unsupported,
+    Code::UndeclaredVariable,
+    Code::UninitializedVariable,
+    Code::RedeclaredVariable,
+    Code::MissingReturn,
+    Code::TypeMismatch,
+    Code::IncomparableTypes,
+    Code::OutsideLoop,
+    Code::DeclarationInForStep,
+    Code::InvalidCharacter,
+    Code::IncrementInExpression,
+    Code::IgnoredOption,
+    Code::OptimizationReport,
+];
+
+impl Code {
+    /// Looks up the code named `name`, such as `E0001`.
+    pub fn from_name(name: &str) -> Option<Code> {
+        ALL.iter().cloned().find(|c| c.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Code::UnclosedComment => "E0001",
+            Code::ConstantTooLarge => "E0002",
+            Code::UnexpectedToken => "E0003",
+            Code::UnexpectedEof => "E0004",
+            Code::ExtraToken => "E0005",
+            Code::Unsupported => "E0006",
+            Code::UndeclaredVariable => "E0007",
+            Code::UninitializedVariable => "E0008",
+            Code::RedeclaredVariable => "E0009",
+            Code::MissingReturn => "E0010",
+            Code::TypeMismatch => "E0011",
+            Code::IncomparableTypes => "E0012",
+            Code::OutsideLoop => "E0013",
+            Code::DeclarationInForStep => "E0014",
+            Code::InvalidCharacter => "E0015",
+            Code::IncrementInExpression => "E0016",
+            Code::IgnoredOption => "E0017",
+            Code::OptimizationReport => "E0018",
+        }
+    }
+
+    /// Returns a longer description of the diagnostic, printed by
+    /// `--explain`.
+    pub fn explanation(&self) -> &'static str {
+        match *self {
+            Code::UnclosedComment => "\
+A block comment was still open at the end of the file.
+
+Block comments nest, so every `/*` needs its own `*/`:
+
+    /* outer /* inner */
+    int main() { return 0; }
+
+Here the outer comment is never closed.",
+
+            Code::ConstantTooLarge => "\
+An integer constant does not fit in 32 bits.
+
+Decimal constants may be at most 2147483648, and hexadecimal constants at
+most 0xffffffff.",
+
+            Code::UnexpectedToken => "\
+The parser found a token which cannot appear at this point in the program.
+
+    int main() {
+        return 1 +;
+    }
+
+The message lists the tokens which could have appeared instead.",
+
+            Code::UnexpectedEof => "\
+The file ended before the program was complete, usually because of a missing
+`}` or `;`.",
+
+            Code::ExtraToken => "\
+There is more code after the closing brace of `main`. An L1 program consists
+of `main` alone.",
+
+            Code::Unsupported => "\
+This error code is no longer emitted by the compiler.
+
+It was reported for syntax which the compiler did not support yet, which was
+last the case for `x--` statements.",
+
+            Code::UndeclaredVariable => "\
+A variable was used or assigned without being declared first, or outside of
+the block which declares it.
+
+    int main() {
+        { int x = 1; }
+        return x;
+    }",
+
+            Code::UninitializedVariable => "\
+A variable may be used before it has been assigned a value.
+
+Every path through the program leading to the use has to assign the variable:
+
+    int main() {
+        int x;
+        if (true) x = 1;
+        return x;
+    }
+
+Here `x` is not assigned when the condition is false. The body of a loop might
+never run, so assignments inside it do not count after the loop.",
+
+            Code::RedeclaredVariable => "\
+A variable was declared while a variable with the same name was in scope.
+Variables may not be shadowed.
+
+    int main() {
+        int x = 1;
+        { int x = 2; }
+        return x;
+    }",
+
+            Code::MissingReturn => "\
+Some path through `main` reaches its end without a `return`.
+
+    int main() {
+        if (true) return 1;
+    }
+
+A loop does not count as returning, even if its body does.",
+
+            Code::TypeMismatch => "\
+An expression has a different type from the one its context expects, such as
+a `bool` used in arithmetic or an `int` used as a condition.
+
+    int main() {
+        int x = 1;
+        if (x) return 1;
+        return 0;
+    }
+
+Compare with zero instead: `if (x != 0)`.",
+
+            Code::IncomparableTypes => "\
+The two sides of `==` or `!=` have different types.
+
+    int main() {
+        if (1 == true) return 1;
+        return 0;
+    }",
+
+            Code::OutsideLoop => "\
+A `break` or `continue` appears outside of any `while` or `for` loop.",
+
+            Code::DeclarationInForStep => "\
+The step of a `for` loop, run after each iteration, declares a variable. The
+step may only be an assignment.
+
+    for (int i = 0; i < 10; int j = i) { }",
+
+            Code::InvalidCharacter => "\
+The program contains a character which is not part of any token, such as `@`
+or `$`, outside of a comment.",
+
+            Code::IncrementInExpression => "\
+`++` or `--` appears inside an expression. They can only be used as statements
+of their own, which add or subtract one from a variable:
+
+    int main() {
+        int x = 1;
+        x++;
+        return x;
+    }
+
+Since `--` is always read as a single token, `--x` does not negate `x` twice.
+Write `- -x` or `-(-x)` instead.",
+
+            Code::IgnoredOption => "\
+A command line option was given which has no effect with the other options,
+such as `--dump-ssa` without `-O1`, since the SSA form is only built for the
+optimizations. This is a warning, and the program is still compiled.",
+
+            Code::OptimizationReport => "\
+With `-v`, the compiler notes how much code the optimizations removed. This is
+not a problem with the program.",
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -1,41 +1,81 @@
 //! A utility module to collect errors and print them.
 
 use std::cell::Cell;
+use std::cmp;
+use std::fmt;
 use std::io::{self, Write};
 use std::process;
 
-use util::mark::{Mark, CodeMap, DUMMY_MARK};
+use util::codes::Code;
+use util::mark::{Mark, CodeMap};
 
 #[derive(Clone)]
 pub struct Errors {
     cm: CodeMap,
+    format: ErrorFormat,
     errored: Cell<bool>,
 }
 
+/// How diagnostics are printed.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ErrorFormat {
+    /// Messages with snippets of the code, for people
+    Human,
+    /// One JSON object per line, for tools
+    Json,
+}
+
+/// How serious a diagnostic is. Only errors stop compilation.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A message about the program, pointing at the code it concerns.
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    /// The code the message is about, or `None` for the program as a whole
+    pub mark: Option<Mark>,
+    pub msg: String,
+    /// Other code relevant to the message, along with why it is relevant
+    pub labels: Vec<(Mark, String)>,
+    /// Suggestions on how to fix the problem
+    pub hints: Vec<String>,
+}
+
 impl Errors {
     /// Creates a new structure which will track errors and print them for the
     /// code map specified.
-    pub fn new(cm: CodeMap) -> Errors {
-        Errors { cm: cm, errored: Cell::new(false) }
+    pub fn new(cm: CodeMap, format: ErrorFormat) -> Errors {
+        Errors { cm, format, errored: Cell::new(false) }
     }
 
     /// Emit an error for the specified `Mark` (location in the program).
     ///
     /// This does not abort compilation to allow more errors to be printed.
-    pub fn add(&self, m: &Mark, msg: &str) {
+    pub fn add(&self, code: Code, m: &Mark, msg: &str) {
+        self.emit(Diagnostic::new(Severity::Error, code, m, msg));
+    }
+
+    /// Emit a diagnostic, showing the code it points at.
+    pub fn emit(&self, d: Diagnostic) {
         let mut out = io::stderr();
-        let mut msg = format!("error: {}\n", msg);
-        if *m != DUMMY_MARK {
-            msg = format!("{}:{}:{}", self.cm.file().display(),
-                          m.to_string(&self.cm), msg)
+        let msg = match self.format {
+            ErrorFormat::Human => self.render(&d),
+            ErrorFormat::Json => self.json(&d),
+        };
+        out.write_all(msg.as_bytes()).unwrap();
+        if d.severity == Severity::Error {
+            self.errored.set(true);
         }
-        out.write(msg.as_bytes()).unwrap();
-        self.errored.set(true);
     }
 
     /// Emit an error, and at the same time abort the program.
-    pub fn die(&self, m: &Mark, msg: &str) -> ! {
-        self.add(m, msg);
+    pub fn die(&self, code: Code, m: &Mark, msg: &str) -> ! {
+        self.add(code, m, msg);
         die()
     }
 
@@ -46,6 +86,176 @@ impl Errors {
             die();
         }
     }
+
+    /// Formats `d` along the lines of:
+    ///
+    /// ```text
+    /// error[E0009]: redeclared variable `x`
+    ///  --> foo.l1:3:3-3:13
+    ///   |
+    /// 3 |   int x = 2;
+    ///   |   ^^^^^^^^^^
+    /// 2 |   int x = 1;
+    ///   |   ---------- previously declared here
+    /// ```
+    fn render(&self, d: &Diagnostic) -> String {
+        let mut out = format!("{}[{}]: {}\n", d.severity, d.code, d.msg);
+        let mark = match d.mark {
+            Some(mark) => mark,
+            None => {
+                for hint in d.hints.iter() {
+                    out.push_str(&format!("  = hint: {}\n", hint));
+                }
+                return out
+            }
+        };
+
+        // Line numbers are right-aligned in a gutter wide enough for all of
+        // them
+        let last = d.labels.iter().map(|(m, _)| m)
+            .chain(Some(&mark))
+            .map(|m| self.cm.linecol(m.hi).0)
+            .max().unwrap();
+        let width = last.to_string().len();
+
+        out.push_str(&format!("{:w$}--> {}:{}\n", "", self.cm.file().display(),
+                              mark.to_string(&self.cm), w = width));
+        out.push_str(&format!("{:w$} |\n", "", w = width));
+        self.snippet(&mut out, &mark, '^', "", width);
+        for (m, label) in d.labels.iter() {
+            self.snippet(&mut out, m, '-', label, width);
+        }
+        for hint in d.hints.iter() {
+            out.push_str(&format!("{:w$} = hint: {}\n", "", hint, w = width));
+        }
+        out.push('\n');
+        out
+    }
+
+    /// Formats `d` as a single line of JSON, such as:
+    ///
+    /// ```text
+    /// {"file":"foo.l1","span":{"lo":37,"hi":38,"line_start":3,
+    ///  "column_start":10,"line_end":3,"column_end":11},"severity":"error",
+    ///  "code":"E0007","message":"undeclared variable `y`","labels":[],
+    ///  "hints":[]}
+    /// ```
+    ///
+    /// The span is `null` for diagnostics about the program as a whole.
+    fn json(&self, d: &Diagnostic) -> String {
+        let labels = d.labels.iter().map(|(m, msg)| {
+            format!("{{\"span\":{},\"message\":{}}}",
+                    self.json_span(m), json_string(msg))
+        }).collect::<Vec<_>>();
+        let hints = d.hints.iter().map(|h| json_string(h))
+            .collect::<Vec<_>>();
+        format!("{{\"file\":{},\"span\":{},\"severity\":\"{}\",\
+                 \"code\":\"{}\",\"message\":{},\"labels\":[{}],\
+                 \"hints\":[{}]}}\n",
+                json_string(&self.cm.file().display().to_string()),
+                d.mark.map_or(String::from("null"), |m| self.json_span(&m)),
+                d.severity, d.code,
+                json_string(&d.msg), labels.join(","), hints.join(","))
+    }
+
+    fn json_span(&self, m: &Mark) -> String {
+        let (loline, locol) = self.cm.linecol(m.lo);
+        let (hiline, hicol) = self.cm.linecol(m.hi);
+        format!("{{\"lo\":{},\"hi\":{},\"line_start\":{},\"column_start\":{},\
+                 \"line_end\":{},\"column_end\":{}}}",
+                m.lo, m.hi, loline, locol, hiline, hicol)
+    }
+
+    /// Appends the lines of code spanned by `m` to `out`, underlining the
+    /// marked part with `ch` and following the underline with `label`.
+    fn snippet(&self, out: &mut String, m: &Mark, ch: char, label: &str,
+               width: usize) {
+        let (loline, locol) = self.cm.linecol(m.lo);
+        let (hiline, hicol) = self.cm.linecol(m.hi);
+        for line in loline..hiline + 1 {
+            let text = self.cm.line(line);
+            let start = if line == loline { locol - 1 } else { 0 };
+            let end = if line == hiline { hicol - 1 } else { text.len() };
+
+            // Tabs are kept so the underline stays aligned with the code
+            let indent = text.bytes().take(start)
+                .map(|b| if b == b'\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let underline = (0..cmp::max(end, start + 1) - start)
+                .map(|_| ch).collect::<String>();
+            out.push_str(&format!("{:>w$} | {}\n", line, text, w = width));
+            out.push_str(&format!("{:w$} | {}{}", "", indent, underline,
+                                  w = width));
+            if line == hiline && !label.is_empty() {
+                out.push_str(&format!(" {}", label));
+            }
+            out.push('\n');
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: Code, m: &Mark, msg: &str)
+               -> Diagnostic {
+        Diagnostic {
+            mark: Some(*m),
+            ..Diagnostic::unmarked(severity, code, msg)
+        }
+    }
+
+    /// Creates a diagnostic about the program as a whole, rather than a
+    /// particular piece of its code.
+    pub fn unmarked(severity: Severity, code: Code, msg: &str) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            mark: None,
+            msg: msg.to_string(),
+            labels: Vec::new(),
+            hints: Vec::new(),
+        }
+    }
+
+    /// Points out other code relevant to this diagnostic.
+    pub fn label(mut self, m: &Mark, msg: &str) -> Diagnostic {
+        self.labels.push((*m, msg.to_string()));
+        self
+    }
+
+    /// Suggests how to fix the problem.
+    pub fn hint(mut self, msg: &str) -> Diagnostic {
+        self.hints.push(msg.to_string());
+        self
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
 
 fn die() -> ! {
@@ -0,0 +1,32 @@
+//! IR Labels
+
+use std::fmt;
+use std::cell::Cell;
+
+/// A label in the IR, marking a position which can be jumped to.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Copy)]
+pub struct Label(usize);
+
+/// An allocator of Labels, used during translation.
+pub struct LabelAllocator {
+    next: Cell<usize>,
+}
+
+impl LabelAllocator {
+    /// Prepares a new allocator ready to create new labels
+    pub fn new() -> LabelAllocator { LabelAllocator { next: Cell::new(0) } }
+
+    /// Generates a new unique label
+    pub fn gen(&self) -> Label {
+        let ret = self.next.get();
+        self.next.set(ret + 1);
+        Label(ret)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Label(i) = *self;
+        write!(f, ".L{}", i)
+    }
+}
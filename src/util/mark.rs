@@ -32,18 +32,15 @@ pub struct CodeMap {
     file: PathBuf,
 }
 
-/// A dummy span to represent the "entire program"
-pub static DUMMY_MARK: Mark = Mark { lo: 0, hi: 0 };
-
 impl Mark {
     /// Creates a new `Mark` which is bounded by `lo` and `hi` in the source
     /// code of the original program.
     pub fn new(lo: usize, hi: usize) -> Mark {
-        Mark { lo: lo, hi: hi }
+        Mark { lo, hi }
     }
 
     /// Converts this `Mark` to a string given the specified code map.
-    pub fn to_string(&self, cm: &CodeMap) -> String {
+    pub fn to_string(self, cm: &CodeMap) -> String {
         let (loline, locol) = cm.linecol(self.lo);
         let (hiline, hicol) = cm.linecol(self.hi);
         format!("{}:{}-{}:{}", loline, locol, hiline, hicol)
@@ -53,7 +50,7 @@ impl Mark {
 impl<T> Marked<T> {
     /// Helper function for creating a new instance of a marked node.
     pub fn new(t: T, mark: Mark) -> Marked<T> {
-        Marked { node: t, mark: mark }
+        Marked { node: t, mark }
     }
 
     /// Unwrap the inner value contained within this marked node.
@@ -81,7 +78,7 @@ impl<T: fmt::Display> fmt::Display for Marked<T> {
 impl CodeMap {
     /// Creates a new code map for the program in question.
     pub fn new(code: String, file: PathBuf) -> CodeMap {
-        CodeMap { code: code, file: file }
+        CodeMap { code, file }
     }
 
     /// Converts a bytes offset of a `Mark` into a (line, column) pair.
@@ -90,7 +87,7 @@ impl CodeMap {
     pub fn linecol(&self, offset: usize) -> (usize, usize) {
         let mut cur = 0;
         for (i, line) in self.code.split('\n').enumerate() {
-            if cur + line.len() > offset {
+            if cur + line.len() >= offset {
                 return (i + 1, offset + 1 - cur)
             }
             cur += line.len() + 1;
@@ -98,6 +95,12 @@ impl CodeMap {
         (self.code.split('\n').count() + 1, 1)
     }
 
+    /// Returns the text of the 1-based line `line`, without its newline, or
+    /// an empty string if there is no such line.
+    pub fn line(&self, line: usize) -> &str {
+        self.code.split('\n').nth(line.wrapping_sub(1)).unwrap_or("")
+    }
+
     /// Returns the file that this code map represents.
     pub fn file(&self) -> &Path { &self.file }
 }
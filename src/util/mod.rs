@@ -1,9 +1,13 @@
 pub use util::symbol::{Symbol, SymbolGenerator};
-pub use util::errors::Errors;
-pub use util::mark::{Mark, Marked, CodeMap, DUMMY_MARK};
+pub use util::codes::Code;
+pub use util::errors::{Errors, ErrorFormat, Diagnostic, Severity};
+pub use util::mark::{Mark, Marked, CodeMap};
 pub use util::temp::{Temp, TempAllocator};
+pub use util::label::{Label, LabelAllocator};
 
 mod symbol;
+mod codes;
 mod errors;
 mod mark;
 mod temp;
+mod label;
@@ -29,7 +29,8 @@ pub struct SymbolGenerator {
     table: HashMap<String, Symbol>,
 }
 
-thread_local!(static SYMBOLS: RefCell<Vec<String>> = RefCell::new(Vec::new()));
+thread_local!(static SYMBOLS: RefCell<Vec<String>> =
+              const { RefCell::new(Vec::new()) });
 
 impl SymbolGenerator {
     /// Creates a new empty symbol generator ready to generate new symbols.
@@ -49,7 +50,7 @@ impl SymbolGenerator {
         let s = String::from(s);
         self.table.insert(s.clone(), ret);
         self.symbols.push(s);
-        return ret
+        ret
     }
 
     /// Consume ownership of this Generator, storing the symbol table in
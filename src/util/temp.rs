@@ -18,12 +18,6 @@ impl TempAllocator {
     /// Prepares a new allocator ready to create new temporaries
     pub fn new() -> TempAllocator { TempAllocator { next: Cell::new(0) } }
 
-    /// Returns the number of temporaries allocated so far
-    pub fn count(&self) -> usize { self.next.get() }
-
-    /// Resets the allocator back to 0
-    pub fn reset(&self) { self.next.set(0); }
-
     /// Generates a new unique temporary
     pub fn gen(&self) -> Temp {
         let ret = self.next.get();
@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Returns a command which runs the compiler.
+///
+/// Cargo builds the compiler next to the `deps` directory holding the test
+/// executables. Looking for it there, rather than with
+/// `CARGO_BIN_EXE_l1-compiler`, also works with versions of Cargo older than
+/// 1.43, which the toolchain needed by the old rustlex lexer came with.
+pub fn compiler() -> Command {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    Command::new(path.join("l1-compiler"))
+}
+
+/// Writes `code` to the file `name` in the directory `dir` under the system's
+/// temporary directory, and runs the compiler on it with the command line
+/// arguments `args`. Returns the path of the file along with what the
+/// compiler printed.
+pub fn invoke(dir: &str, name: &str, code: &str, args: &[&str])
+              -> (PathBuf, Output) {
+    let dir = env::temp_dir().join(dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    File::create(&path).unwrap().write_all(code.as_bytes()).unwrap();
+
+    let output = compiler().args(args).arg(&path).output().unwrap();
+    (path, output)
+}
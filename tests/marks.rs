@@ -0,0 +1,407 @@
+//! Checks that errors are reported at the source location which caused them.
+
+mod common;
+
+/// Typechecks `code` as the file `name`, returning what the compiler printed
+/// to stderr along with the path of the file.
+fn errors(name: &str, code: &str) -> (String, String) {
+    errors_with(name, code, &[])
+}
+
+/// Like `errors`, passing the extra command line arguments `args`.
+fn errors_with(name: &str, code: &str, args: &[&str]) -> (String, String) {
+    let mut all = vec!["-t"];
+    all.extend_from_slice(args);
+    let (path, output) = common::invoke("l1-compiler-marks", name, code, &all);
+    assert!(!output.status.success());
+    (String::from_utf8(output.stderr).unwrap(),
+     path.display().to_string())
+}
+
+/// Checks that compiling `code` reports the error `err` with message `msg` at
+/// `span`.
+fn assert_error(name: &str, code: &str, span: &str, err: &str, msg: &str) {
+    let (stderr, path) = errors(name, code);
+    let header = format!("error[{}]: {}", err, msg);
+    let location = format!("--> {}:{}", path, span);
+    let lines = stderr.lines().collect::<Vec<_>>();
+    assert!(lines.windows(2).any(|w| w[0] == header &&
+                                     w[1].trim_start() == location),
+            "expected `{}` at `{}` in:\n{}", header, location, stderr);
+}
+
+#[test]
+fn undeclared_variable() {
+    assert_error("undeclared.l1",
+                 "int main() {\n  return x;\n}\n",
+                 "2:10-2:11", "E0007",
+                 "undeclared variable `x`");
+}
+
+#[test]
+fn undeclared_assignment() {
+    assert_error("undeclared_assign.l1",
+                 "int main() {\n  x = 1;\n  return 0;\n}\n",
+                 "2:3-2:9", "E0007",
+                 "undeclared variable `x`");
+}
+
+#[test]
+fn uninitialized_variable() {
+    assert_error("uninitialized.l1",
+                 "int main() {\n  int x;\n  if (true) x = 1;\n  return x + 1;\n}\n",
+                 "4:10-4:11", "E0008",
+                 "uninitialized variable `x`");
+}
+
+#[test]
+fn uninitialized_in_own_initializer() {
+    let (stderr, path) = errors("self_init.l1",
+                                "int main() {\n  int x = x + 1;\n  \
+                                 return x;\n}\n");
+    let headers = stderr.lines()
+        .filter(|l| l.starts_with("error"))
+        .collect::<Vec<_>>();
+    assert_eq!(headers, vec!["error[E0008]: uninitialized variable `x`"]);
+    assert!(stderr.contains(&format!("--> {}:2:11-2:12", path)),
+            "found:\n{}", stderr);
+}
+
+#[test]
+fn redeclared_variable() {
+    assert_error("redeclared.l1",
+                 "int main() {\n  int x = 1;\n  int x = 2;\n  return x;\n}\n",
+                 "3:3-3:13", "E0009",
+                 "redeclared variable `x`");
+}
+
+#[test]
+fn type_mismatch() {
+    assert_error("mismatch.l1",
+                 "int main() {\n  int x = 1 + true;\n  return x;\n}\n",
+                 "2:15-2:19", "E0011",
+                 "expected an expression of type `int`, found `bool`");
+}
+
+#[test]
+fn comparison_mismatch() {
+    assert_error("compare.l1",
+                 "int main() {\n  if (1 == false) return 1;\n  return 0;\n}\n",
+                 "2:7-2:17", "E0012",
+                 "cannot compare `int` with `bool`");
+}
+
+#[test]
+fn break_outside_loop() {
+    assert_error("break.l1",
+                 "int main() {\n  break;\n  return 0;\n}\n",
+                 "2:3-2:9", "E0013",
+                 "`break` outside of a loop");
+}
+
+#[test]
+fn continue_outside_loop() {
+    assert_error("continue.l1",
+                 "int main() {\n  continue;\n  return 0;\n}\n",
+                 "2:3-2:12", "E0013",
+                 "`continue` outside of a loop");
+    let (stderr, _) = errors_with("continue_json.l1",
+                                  "int main() {\n  continue;\n  \
+                                   return 0;\n}\n",
+                                  &["--error-format=json"]);
+    assert_eq!(stderr.lines().count(), 1, "found:\n{}", stderr);
+}
+
+#[test]
+fn declaration_in_for_step() {
+    assert_error("for_step.l1",
+                 "int main() {\n  for (int i = 0; i < 1; int j = 0) {}\n  return 0;\n}\n",
+                 "2:26-2:35", "E0014",
+                 "the step of a for loop cannot be a declaration");
+}
+
+#[test]
+fn nested_expression() {
+    assert_error("nested.l1",
+                 "int main() {\n  return (1 + (2 * y)) - 3;\n}\n",
+                 "2:20-2:21", "E0007",
+                 "undeclared variable `y`");
+}
+
+#[test]
+fn snippet_with_label() {
+    let (stderr, path) = errors("snippet.l1",
+                                "int main() {\n  int x = 1;\n  int x = 2;\n  \
+                                 return x;\n}\n");
+    let expected = format!("\
+error[E0009]: redeclared variable `x`
+ --> {}:3:3-3:13
+  |
+3 |   int x = 2;
+  |   ^^^^^^^^^^
+2 |   int x = 1;
+  |   ---------- previously declared here
+", path);
+    assert!(stderr.contains(&expected),
+            "expected:\n{}\nfound:\n{}", expected, stderr);
+}
+
+#[test]
+fn multiline_snippet() {
+    let (stderr, _) = errors("multiline.l1",
+                             "int main() {\n  if (1\n      == true) return 1;\n  \
+                              return 0;\n}\n");
+    assert!(stderr.contains("\
+2 |   if (1
+  |       ^
+3 |       == true) return 1;
+  | ^^^^^^^^^^^^^
+"), "found:\n{}", stderr);
+}
+
+#[test]
+fn json_format() {
+    let (stderr, path) = errors_with("json.l1",
+                                     "int main() {\n  int x;\n  int x;\n  \
+                                      return y;\n}\n",
+                                     &["--error-format=json"]);
+    let lines = stderr.lines().collect::<Vec<_>>();
+    assert_eq!(lines, vec![
+        format!("{{\"file\":\"{}\",\"span\":{{\"lo\":24,\"hi\":30,\
+                 \"line_start\":3,\"column_start\":3,\"line_end\":3,\
+                 \"column_end\":9}},\"severity\":\"error\",\
+                 \"code\":\"E0009\",\"message\":\"redeclared variable `x`\",\
+                 \"labels\":[{{\"span\":{{\"lo\":15,\"hi\":21,\
+                 \"line_start\":2,\"column_start\":3,\"line_end\":2,\
+                 \"column_end\":9}},\"message\":\"previously declared here\"}}],\"hints\":[]}}",
+                path),
+        format!("{{\"file\":\"{}\",\"span\":{{\"lo\":40,\"hi\":41,\
+                 \"line_start\":4,\"column_start\":10,\"line_end\":4,\
+                 \"column_end\":11}},\"severity\":\"error\",\
+                 \"code\":\"E0007\",\"message\":\"undeclared variable `y`\",\"labels\":[],\
+                 \"hints\":[]}}",
+                path),
+    ]);
+}
+
+#[test]
+fn constant_too_large() {
+    assert_error("constant.l1",
+                 "int main() {\n  return 2147483649;\n}\n",
+                 "2:10-2:20", "E0002",
+                 "Constant 2147483649 is too large");
+}
+
+#[test]
+fn unexpected_token() {
+    let (stderr, path) = errors("unexpected.l1",
+                                "int main() {\n  return 1 +;\n}\n");
+    assert!(stderr.starts_with("error[E0003]: "), "found:\n{}", stderr);
+    assert!(stderr.contains(&format!("--> {}:2:13-2:14", path)),
+            "found:\n{}", stderr);
+}
+
+#[test]
+fn missing_return() {
+    assert_error("return.l1",
+                 "int main() {\n  if (true) return 1;\n}\n",
+                 "3:1-3:2", "E0010", "main does not return along every path");
+}
+
+#[test]
+fn explain() {
+    let output = common::compiler()
+        .arg("--explain").arg("E0013")
+        .output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap()
+            .starts_with("A `break` or `continue` appears outside"));
+
+    let output = common::compiler()
+        .arg("--explain").arg("E0006")
+        .output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap()
+            .starts_with("This error code is no longer emitted"));
+
+    let output = common::compiler()
+        .arg("--explain").arg("E9999")
+        .output().unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn recovers_from_syntax_errors() {
+    let (stderr, path) = errors("recover.l1",
+                                "int main() {\n  int x = 1 +;\n  \
+                                 { x = 3 }\n  bool b = 1;\n  return x;\n}\n");
+    let headers = stderr.lines()
+        .filter(|l| l.starts_with("error"))
+        .map(|l| &l[..12])
+        .collect::<Vec<_>>();
+    assert_eq!(headers, vec!["error[E0003]", "error[E0003]", "error[E0011]"]);
+    assert!(stderr.contains(&format!("--> {}:2:14-2:15", path)));
+    assert!(stderr.contains(&format!("--> {}:3:11-3:12", path)));
+    assert!(stderr.contains(&format!("--> {}:4:12-4:13", path)));
+}
+
+#[test]
+fn recovers_inside_the_block() {
+    let (stderr, path) = errors("recover_for.l1",
+                                "int main() { for(;;) { } return 0; }\n");
+    let headers = stderr.lines()
+        .filter(|l| l.starts_with("error"))
+        .collect::<Vec<_>>();
+    assert_eq!(headers, vec!["error[E0003]: expected an expression, found `;`"]);
+    assert!(stderr.contains(&format!("--> {}:1:19-1:20", path)));
+
+    let (stderr, _) = errors("recover_while.l1",
+                             "int main() {\n  while (1 +) { int y = 2; }\n  \
+                              return true;\n}\n");
+    let headers = stderr.lines()
+        .filter(|l| l.starts_with("error"))
+        .map(|l| &l[..12])
+        .collect::<Vec<_>>();
+    assert_eq!(headers, vec!["error[E0003]", "error[E0011]"]);
+}
+
+#[test]
+fn friendly_parse_errors() {
+    assert_error("expected_expr.l1",
+                 "int main() {\n  return 1 +;\n}\n",
+                 "2:13-2:14", "E0003",
+                 "expected an expression, found `;`");
+    assert_error("eof.l1",
+                 "int main() {\n  return 0;\n",
+                 "2:12-2:12", "E0004",
+                 "expected a statement, `else` or `}`, found end of file");
+}
+
+#[test]
+fn unexpected_hex_constant() {
+    assert_error("unexpected_hex.l1",
+                 "int main() {\n  return 1 0xFF;\n}\n",
+                 "2:12-2:16", "E0003",
+                 "expected an operator, `:`, `)` or `;`, found `0xFF`");
+}
+
+#[test]
+fn empty_file() {
+    assert_error("empty.l1", "", "1:1-1:1", "E0004",
+                 "expected `int`, found end of file");
+    let (stderr, _) = errors_with("empty_json.l1", "",
+                                  &["--error-format=json"]);
+    assert!(stderr.contains("\"span\":{\"lo\":0,\"hi\":0,\"line_start\":1,\
+                             \"column_start\":1,\"line_end\":1,\
+                             \"column_end\":1}"),
+            "found:\n{}", stderr);
+}
+
+#[test]
+fn missing_semicolon_hint() {
+    let (stderr, _) = errors("semi.l1",
+                             "int main() {\n  int x = 1\n  return x;\n}\n");
+    assert!(stderr.contains("\
+3 |   return x;
+  |   ^^^^^^
+2 |   int x = 1
+  |            - add `;` here
+"), "found:\n{}", stderr);
+}
+
+#[test]
+fn assignment_in_condition_hint() {
+    let (stderr, _) = errors("eqeq.l1",
+                             "int main() {\n  int x = 1;\n  \
+                              if (x = 1) return 1;\n  return 0;\n}\n");
+    assert!(stderr.contains("found `=`"), "found:\n{}", stderr);
+    assert!(stderr.contains("= hint: use `==` to compare values"),
+            "found:\n{}", stderr);
+}
+
+#[test]
+fn invalid_character() {
+    assert_error("invalid_char.l1",
+                 "int main() {\n  int x = 1 @ 2;\n  return x;\n}\n",
+                 "2:13-2:14", "E0015", "invalid character `@`");
+}
+
+#[test]
+fn hex_constant_too_large() {
+    assert_error("hex.l1",
+                 "int main() {\n  return 0x100000000;\n}\n",
+                 "2:10-2:21", "E0002", "Constant 0x100000000 is too large");
+}
+
+#[test]
+fn unclosed_comment() {
+    assert_error("comment.l1",
+                 "int main() {\n  /* a /* b */\n  return 0;\n}\n",
+                 "2:3-2:5", "E0001", "Unclosed block comment");
+}
+
+#[test]
+fn lexer_errors_do_not_stop_typechecking() {
+    let (stderr, _) = errors("lex_then_type.l1",
+                             "int main() {\n  int x = $1;\n  bool b = 1;\n  \
+                              return x;\n}\n");
+    assert!(stderr.contains("error[E0015]: invalid character `$`"),
+            "found:\n{}", stderr);
+    assert!(stderr.contains("error[E0011]: expected an expression of type \
+                             `bool`, found `int`"),
+            "found:\n{}", stderr);
+}
+
+#[test]
+fn increment_in_expression() {
+    assert_error("postfix.l1",
+                 "int main() {\n  int x = 1;\n  int y = x++;\n  return y;\n}\n",
+                 "3:12-3:14", "E0016", "`++` cannot be used in an expression");
+}
+
+#[test]
+fn decrement_is_not_double_negation() {
+    let (stderr, _) = errors("prefix.l1",
+                             "int main() {\n  int x = 1;\n  return --x;\n}\n");
+    assert!(stderr.contains("error[E0016]: `--` cannot be used in an \
+                             expression"),
+            "found:\n{}", stderr);
+    assert!(stderr.contains("hint: write `- -` to negate twice"),
+            "found:\n{}", stderr);
+}
+
+#[test]
+fn increment_needs_initialized_int() {
+    assert_error("postfix_bool.l1",
+                 "int main() {\n  bool b = true;\n  b++;\n  return 0;\n}\n",
+                 "3:3-3:7", "E0011",
+                 "cannot apply `++` to a variable of type `bool`");
+    assert_error("postfix_uninit.l1",
+                 "int main() {\n  int x;\n  x--;\n  return 0;\n}\n",
+                 "3:3-3:7", "E0008", "uninitialized variable `x`");
+}
+
+#[test]
+fn ternary_branch_mismatch() {
+    assert_error("ternary.l1",
+                 "int main() {\n  int x = 1;\n  return x > 0 ? x : true;\n}\n",
+                 "3:10-3:26", "E0011",
+                 "the branches of `?:` have different types `int` and \
+                  `bool`");
+}
+
+#[test]
+fn unknown_error_format() {
+    let (stderr, _) = errors_with("format.l1", "int main() {\n  return 0;\n}\n",
+                                  &["--error-format=xml"]);
+    assert_eq!(stderr, "unknown error format `xml`, expected `human` or \
+                        `json`\n");
+}
+
+#[test]
+fn unknown_optimization_level() {
+    let (stderr, _) = errors_with("level.l1", "int main() {\n  return 0;\n}\n",
+                                  &["-Ofast"]);
+    assert_eq!(stderr, "unknown optimization level `fast`, expected a \
+                        number such as `-O0` or `-O1`\n");
+}
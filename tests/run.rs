@@ -0,0 +1,344 @@
+//! Compiles programs, links them against the runtime harness and checks how
+//! they finish when run.
+
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Signal number of SIGFPE on Linux.
+const SIGFPE: i32 = 8;
+
+/// Compiles `code` as the file `name`, passing the extra command line
+/// arguments `args` to the compiler, and returns the path of the assembly.
+fn compile(name: &str, code: &str, args: &[&str]) -> PathBuf {
+    let (path, output) = common::invoke("l1-compiler-run", name, code, args);
+    assert!(output.status.success(), "failed to compile:\n{}",
+            String::from_utf8_lossy(&output.stderr));
+    path.with_extension("s")
+}
+
+/// Compiles and runs `code` as the file `name`, passing the extra command line
+/// arguments `args` to the compiler.
+fn run_with(name: &str, code: &str, args: &[&str]) -> Output {
+    let path = compile(name, code, args);
+    let exe = path.with_extension("");
+    let harness = concat!(env!("CARGO_MANIFEST_DIR"), "/runtime/harness.c");
+    let status = Command::new("gcc")
+        .arg(harness).arg(&path).arg("-o").arg(&exe)
+        .status().unwrap();
+    assert!(status.success());
+    Command::new(&exe).output().unwrap()
+}
+
+/// Checks that running `code` prints `result`.
+fn assert_result(name: &str, code: &str, result: &str) {
+    assert_result_with(name, code, &[], result);
+}
+
+/// Like `assert_result`, passing the extra command line arguments `args` to
+/// the compiler.
+fn assert_result_with(name: &str, code: &str, args: &[&str], result: &str) {
+    let output = run_with(name, code, args);
+    assert!(output.status.success(), "`{}` failed with {}", name,
+            output.status);
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), result);
+}
+
+/// Checks that running `code` raises an arithmetic exception.
+fn assert_fpe(name: &str, code: &str) {
+    assert_fpe_with(name, code, &[]);
+}
+
+/// Like `assert_fpe`, passing the extra command line arguments `args` to the
+/// compiler.
+fn assert_fpe_with(name: &str, code: &str, args: &[&str]) {
+    let output = run_with(name, code, args);
+    assert_eq!(output.status.signal(), Some(SIGFPE),
+               "`{}` finished with {}", name, output.status);
+}
+
+#[test]
+fn straight_line_code() {
+    assert_result("straight.l1",
+                  "int main() {\n  int x = 17;\n  int y = x * 3 - 4;\n  \
+                   y /= 5;\n  x = x % 6 + -y;\n  return x * 100 + y;\n}\n",
+                  "-391");
+}
+
+#[test]
+fn spills_live_values() {
+    // Forty values live at once, far more than fit in registers. Nothing is
+    // folded away at -O0.
+    let n = 40;
+    let mut code = String::from("int main() {\n");
+    for i in 0..n {
+        code += &format!("  int v{} = {} * {} - {};\n", i, i * 7 + 3, i + 1, i);
+    }
+    for i in 0..n {
+        code += &format!("  v{} = v{} + v{} * {} - v{};\n",
+                         i, i, (i + 1) % n, i % 5 + 1, (i + 7) % n);
+    }
+    let sum = (0..n).map(|i| format!("v{} * {}", i, i + 1))
+        .collect::<Vec<_>>();
+    code += &format!("  return {};\n}}\n", sum.join(" + "));
+    assert_result("spill.l1", &code, "14409506");
+}
+
+#[test]
+fn loops_with_break_and_continue() {
+    let code = "int main() {\n  int s = 0;\n  int i = 0;\n  \
+                while (true) {\n    i += 1;\n    \
+                if (i % 3 == 0) continue;\n    if (i > 20) break;\n    \
+                for (int j = 0; j < i; j += 1) {\n      \
+                if (j == 4) break;\n      if (j % 2 == 1) continue;\n      \
+                s = s * 3 + j + i;\n    }\n  }\n  return s;\n}\n";
+    assert_result("loops.l1", code, "1151900733");
+}
+
+#[test]
+fn short_circuit_and_ternary() {
+    // The right operand of `&&` and `||` would divide by zero whenever it is
+    // skipped
+    let code = "int main() {\n  int s = 0;\n  \
+                for (int i = 0; i < 8; i++) {\n    bool b = i % 2 == 0;\n    \
+                if (b && 10 / (i - 3) > 1) s += 1;\n    \
+                if (!b || 100 / (i - 5) < 0) s += 10;\n    \
+                if (i == 3 || i / (i - 3) == 1) s += 100;\n    \
+                if (i != 4 && (i < 2 || i > 5) == b) s += 1000;\n    \
+                s = s * 2 + (b ? i : -i) + (i > 3 ? (i > 5 ? 7 : 5) : 1);\n  \
+                }\n  return s;\n}\n";
+    assert_result("logic.l1", code, "308770");
+}
+
+#[test]
+fn division_by_zero() {
+    assert_fpe("div_zero.l1",
+               "int main() {\n  int x = 0;\n  return 1 / x;\n}\n");
+    assert_fpe("mod_zero.l1",
+               "int main() {\n  int x = 0;\n  return 1 % x;\n}\n");
+}
+
+#[test]
+fn division_overflow() {
+    assert_fpe("div_overflow.l1",
+               "int main() {\n  int x = -2147483648;\n  return x / -1;\n}\n");
+    assert_fpe("mod_overflow.l1",
+               "int main() {\n  int x = -2147483648;\n  return x % -1;\n}\n");
+}
+
+#[test]
+fn shift_out_of_range() {
+    assert_fpe("shl_32.l1",
+               "int main() {\n  int x = 32;\n  return 1 << x;\n}\n");
+    assert_fpe("shr_negative.l1",
+               "int main() {\n  int x = -1;\n  return 1 >> x;\n}\n");
+}
+
+#[test]
+fn constant_operands_trap_at_runtime() {
+    assert_fpe("const_div.l1", "int main() {\n  return 1 / 0;\n}\n");
+    assert_fpe("const_mod.l1",
+               "int main() {\n  return -2147483648 % -1;\n}\n");
+    assert_fpe("const_shift.l1", "int main() {\n  return 1 << 32;\n}\n");
+}
+
+#[test]
+fn arithmetic_in_range() {
+    assert_result("div.l1", "int main() {\n  return -7 / 2;\n}\n", "-3");
+    assert_result("mod.l1", "int main() {\n  return -7 % 2;\n}\n", "-1");
+    assert_result("shl_31.l1", "int main() {\n  return 1 << 31;\n}\n",
+                  "-2147483648");
+    assert_result("sar.l1", "int main() {\n  return -8 >> 1;\n}\n", "-4");
+}
+
+#[test]
+fn folding_keeps_arithmetic_exceptions() {
+    let o1 = &["-O1"];
+    assert_fpe_with("fold_div.l1", "int main() {\n  return 1 / 0;\n}\n", o1);
+    assert_fpe_with("fold_mod.l1",
+                    "int main() {\n  return -2147483648 % -1;\n}\n", o1);
+    assert_fpe_with("fold_shift.l1",
+                    "int main() {\n  return 1 >> -1;\n}\n", o1);
+}
+
+#[test]
+fn folding_wraps_around() {
+    let o1 = &["-O1"];
+    assert_result_with("fold_add.l1",
+                       "int main() {\n  return 2147483647 + 1;\n}\n", o1,
+                       "-2147483648");
+    assert_result_with("fold_mul.l1",
+                       "int main() {\n  return 65536 * 65536 + 7 / -2;\n}\n",
+                       o1, "-3");
+    assert_result_with("fold_branch.l1",
+                       "int main() {\n  if (1 << 4 > 15 && -8 >> 1 == -4) \
+                        return 1;\n  return 0;\n}\n", o1, "1");
+}
+
+#[test]
+fn control_flow_through_blocks() {
+    let code = "int main() {\n  int s = 0;\n  \
+                for (int i = 0; i < 10; i++) {\n    \
+                if (i % 3 == 0) continue;\n    \
+                if (i == 8) break;\n    \
+                s += i > 4 ? i * 10 : i;\n  }\n  \
+                while (true) {\n    if (s > 100) return s;\n    s++;\n  }\n  \
+                return 0;\n}\n";
+    assert_result("blocks.l1", code, "127");
+    assert_result_with("blocks_o1.l1", code, &["-O1"], "127");
+}
+
+#[test]
+fn values_swapped_in_loops() {
+    let swap = "int main() {\n  int a = 1;\n  int b = 2;\n  \
+                for (int i = 0; i < 3; i++) {\n    \
+                int t = a;\n    a = b;\n    b = t;\n  }\n  \
+                return a * 10 + b;\n}\n";
+    assert_result("swap.l1", swap, "21");
+    assert_result_with("swap_o1.l1", swap, &["-O1"], "21");
+    let fib = "int main() {\n  int a = 1;\n  int b = 2;\n  int i = 0;\n  \
+               while (i < 5) {\n    int t = a;\n    a = b;\n    \
+               b = t + a;\n    i++;\n    if (i == 5) return a * 100 + b;\n  \
+               }\n  return 0;\n}\n";
+    assert_result("fib.l1", fib, "1321");
+    assert_result_with("fib_o1.l1", fib, &["-O1"], "1321");
+}
+
+#[test]
+fn dead_code_keeps_arithmetic_exceptions() {
+    let o1 = &["-O1"];
+    assert_fpe_with("dead_div.l1",
+                    "int main() {\n  int x = 0;\n  int y = 1 / x;\n  \
+                     return x;\n}\n", o1);
+    assert_fpe_with("dead_shift.l1",
+                    "int main() {\n  int x = 40;\n  int y = 1 << x;\n  \
+                     return x;\n}\n", o1);
+    assert_result_with("dead_stores.l1",
+                       "int main() {\n  int x = 5;\n  int y = x * 3;\n  \
+                        int z = x / 2 + (y << 3);\n  y = y + z;\n  \
+                        return x;\n}\n", o1, "5");
+}
+
+#[test]
+fn copies_propagated_across_loops() {
+    let o1 = &["-O1"];
+    let rotate = "int main() {\n  int a = 1;\n  int b = 2;\n  int c = 3;\n  \
+                  for (int i = 0; i < 4; i++) {\n    int t = a;\n    \
+                  a = b;\n    b = c;\n    c = t;\n  }\n  \
+                  return a * 100 + b * 10 + c;\n}\n";
+    assert_result_with("rotate.l1", rotate, o1, "231");
+    let old = "int main() {\n  int x = 0;\n  int y = 0;\n  while (true) {\n    \
+               y = x;\n    x = x + 1;\n    \
+               if (x >= 5) return y * 10 + x;\n  }\n  return 0;\n}\n";
+    assert_result_with("old_value.l1", old, o1, "45");
+}
+
+#[test]
+fn selected_instructions() {
+    let code = "int main() {\n  int x = 9;\n  int y = 4;\n  \
+                for (int i = 0; i < 6; i++) {\n    x = 5 - x;\n    \
+                y = y * y - x;\n    x = x * 0 + y * 1 - x * 2;\n    \
+                y = ~y + -x + (x << 0) - (y >> 31);\n    \
+                x = x - -2147483648 + y * 4 + i * 9;\n    \
+                y = y % 1000 + x / 7 - 5;\n  }\n  return x * 31 + y;\n}\n";
+    assert_result("munch.l1", code, "804455238");
+    assert_result_with("munch_o1.l1", code, &["-O1"], "804455238");
+}
+
+#[test]
+fn strength_reduction() {
+    let o1 = &["-O1"];
+    let code = "int main() {\n  int s = 0;\n  \
+                for (int x = -20; x <= 20; x += 3) {\n    \
+                s = s * 7 + x / 4 + x % 4 + x / -8 + x % -8;\n    \
+                s = s * 7 + x / 7 + x % 7 + x / -10 + x % -10;\n    \
+                s = s + x * 16 + x * -4 + x * 0;\n  }\n  \
+                int m = -2147483648;\n  \
+                return s + m / 3 + m % 3 + m / 1024 + m / -2147483648;\n}\n";
+    assert_result("reduce.l1", code, "1189436987");
+    assert_result_with("reduce_o1.l1", code, o1, "1189436987");
+    assert_fpe_with("reduce_zero.l1",
+                    "int main() {\n  int x = 5;\n  return x % 0;\n}\n", o1);
+    assert_fpe_with("reduce_overflow.l1",
+                    "int main() {\n  int x = -2147483648;\n  \
+                     return x / -1;\n}\n", o1);
+}
+
+#[test]
+fn peephole_rules() {
+    let code = "int main() {\n  int s = 0;\n  \
+                for (int i = 0; i < 10; i++) {\n    \
+                if (i % 2 == 0) s += i;\n    else s = s * 2 - 1;\n  }\n  \
+                return s;\n}\n";
+    let asm = fs::read_to_string(compile("peephole.l1", code, &["-O1"]))
+        .unwrap();
+    let lines = asm.lines().map(str::trim).collect::<Vec<_>>();
+    for (i, line) in lines.iter().enumerate() {
+        assert!(!line.starts_with("movl $0, %"), "`{}` left in", line);
+        if let Some(target) = line.strip_prefix("jmp ") {
+            assert_ne!(lines[i + 1], format!("{}:", target));
+        }
+    }
+
+    for rule in ["self-move", "move-back", "jump-to-next", "zero-xor", "all"] {
+        assert_result_with(&format!("peephole_no_{}.l1", rule), code,
+                           &["-O1", "--no-peephole", rule], "73");
+    }
+    assert_result_with("peephole_all_rules.l1", code, &["-O1"], "73");
+
+    let (_, output) = common::invoke("l1-compiler-run", "peephole_unknown.l1",
+                                     code, &["-O1", "--no-peephole", "foo"]);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               "unknown peephole rule `foo`, expected one of: all, self-move, \
+                move-back, jump-to-next, zero-xor\n");
+}
+
+#[test]
+fn dump_ssa_before_optimizing() {
+    let code = "int main() {\n  int x = 3;\n  int y = x;\n  return y;\n}\n";
+    let (_, output) = common::invoke("l1-compiler-run", "dump_ssa.l1", code,
+                                     &["-O1", "--dump-ssa"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // The copies are still there, since propagating them comes later
+    assert!(stdout.contains("%t2 <-- 3\n\t%t3 <-- %t2\n\treturn %t3"),
+            "found:\n{}", stdout);
+
+}
+
+#[test]
+fn warnings_do_not_stop_compilation() {
+    let code = "int main() {\n  int x = 3;\n  return x;\n}\n";
+    let (_, output) = common::invoke("l1-compiler-run", "dump_ssa_o0.l1", code,
+                                     &["--dump-ssa"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               "warning[E0017]: `--dump-ssa` has no effect below -O1\n");
+
+    let (_, output) = common::invoke("l1-compiler-run", "dump_ssa_json.l1",
+                                     code, &["--dump-ssa",
+                                             "--error-format=json"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap()
+            .contains("\"span\":null,\"severity\":\"warning\",\
+                       \"code\":\"E0017\""));
+
+    assert_result_with("dump_ssa_run.l1", code, &["--dump-ssa"], "3");
+}
+
+#[test]
+fn verbose_notes() {
+    let code = "int main() {\n  int x = 3;\n  int y = x * 2;\n  \
+                return x;\n}\n";
+    let (_, output) = common::invoke("l1-compiler-run", "verbose.l1", code,
+                                     &["-O1", "-v"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               "note[E0018]: dead code elimination removed 1 instruction and \
+                0 unreachable blocks\n");
+}